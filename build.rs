@@ -0,0 +1,89 @@
+#[cfg(not(feature = "download"))]
+fn main() {}
+
+#[cfg(feature = "download")]
+fn main() {
+    download::start();
+}
+
+/// Downloads the Core Lightning release of the enabled `download_*` feature in `OUT_DIR`, using
+/// the `curl`, `sha256sum` and `tar` executables
+#[cfg(feature = "download")]
+mod download {
+    use std::path::Path;
+    use std::process::Command;
+
+    include!("src/versions.rs");
+
+    /// Release assets are built for Ubuntu, override with `LIGHTNINGD_DOWNLOAD_DISTRO`
+    const DEFAULT_DISTRO: &str = "Ubuntu-22.04";
+
+    fn run(command: &mut Command) {
+        let status = command
+            .status()
+            .unwrap_or_else(|e| panic!("cannot run {:?}: {}", command, e));
+        assert!(status.success(), "{:?} failed with {}", command, status);
+    }
+
+    pub(crate) fn start() {
+        println!("cargo:rerun-if-env-changed=LIGHTNINGD_SKIP_DOWNLOAD");
+        println!("cargo:rerun-if-env-changed=LIGHTNINGD_TARBALL_FILE");
+        println!("cargo:rerun-if-env-changed=LIGHTNINGD_DOWNLOAD_ENDPOINT");
+        println!("cargo:rerun-if-env-changed=LIGHTNINGD_DOWNLOAD_DISTRO");
+        if std::env::var_os("LIGHTNINGD_SKIP_DOWNLOAD").is_some() {
+            return;
+        }
+        let out_dir = std::env::var_os("OUT_DIR").unwrap();
+        let home = Path::new(&out_dir).join("lightningd").join(VERSION);
+        if home.join("usr").join("bin").join("lightningd").exists() {
+            return;
+        }
+        std::fs::create_dir_all(&home).unwrap();
+
+        let distro = std::env::var("LIGHTNINGD_DOWNLOAD_DISTRO")
+            .unwrap_or_else(|_| DEFAULT_DISTRO.to_owned());
+        let filename = format!("clightning-{}-{}.tar.xz", VERSION, distro);
+        let expected = SHA256
+            .iter()
+            .find(|(name, _)| *name == filename)
+            .map(|(_, hash)| *hash)
+            .unwrap_or_else(|| panic!("no sha256 of {} in src/versions.rs", filename));
+        let tarball = match std::env::var("LIGHTNINGD_TARBALL_FILE") {
+            Ok(path) => Path::new(&path).to_path_buf(),
+            Err(_) => {
+                let endpoint = std::env::var("LIGHTNINGD_DOWNLOAD_ENDPOINT").unwrap_or_else(|_| {
+                    "https://github.com/ElementsProject/lightning/releases/download".to_owned()
+                });
+                let url = format!("{}/{}/{}", endpoint, VERSION, filename);
+                run(Command::new("curl")
+                    .args(["--fail", "--location", "--silent", "--show-error"])
+                    .arg("--output")
+                    .arg(home.join(&filename))
+                    .arg(&url));
+                home.join(&filename)
+            }
+        };
+        let output = Command::new("sha256sum")
+            .arg(&tarball)
+            .output()
+            .unwrap_or_else(|e| panic!("cannot run sha256sum: {}", e));
+        assert!(
+            output.status.success(),
+            "sha256sum failed with {}",
+            output.status
+        );
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let actual = stdout.split_whitespace().next().unwrap_or_default();
+        assert_eq!(
+            actual,
+            expected,
+            "sha256 of {} doesn't match the one in src/versions.rs",
+            tarball.display()
+        );
+        run(Command::new("tar")
+            .arg("-xJf")
+            .arg(&tarball)
+            .arg("-C")
+            .arg(&home));
+    }
+}
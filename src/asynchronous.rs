@@ -0,0 +1,187 @@
+//! Async variants of the constructors and of the waiting helpers, enabled by the `async` feature.
+//!
+//! Checks and arguments are shared with the blocking versions, only the pauses between checks use
+//! [tokio::time::sleep] so that the executor isn't blocked. Note the single rpc calls and the
+//! process spawn are still synchronous.
+
+use std::{ffi::OsStr, time::Duration};
+
+use bitcoind::BitcoinD;
+use clightningrpc::LightningRPC;
+
+use crate::{
+    bitcoind_ready, ChannelId, Conf, Error, Launch, LightningD, LogEntry, Waiting, POLL_INTERVAL,
+};
+
+/// Async version of [crate::wait_until]
+async fn wait_until<T>(
+    timeout: Duration,
+    error: Error,
+    mut check: impl FnMut() -> Result<Option<T>, Error>,
+) -> Result<T, Error> {
+    wait(Waiting::new(timeout, error, move |_: &mut String| check())).await
+}
+
+/// Async version of [crate::wait]
+async fn wait<T>(
+    mut waiting: Waiting<impl FnMut(&mut String) -> Result<Option<T>, Error>>,
+) -> Result<T, Error> {
+    loop {
+        match waiting.poll()? {
+            Some(value) => return Ok(value),
+            None => tokio::time::sleep(POLL_INTERVAL).await,
+        }
+    }
+}
+
+impl LightningD {
+    /// Async version of [LightningD::new]
+    pub async fn new_async<S: AsRef<OsStr>>(exe: S, bitcoind: &BitcoinD) -> Result<Self, Error> {
+        let conf = Conf::default();
+        Self::with_conf_async(exe, bitcoind, &conf).await
+    }
+
+    /// Async version of [LightningD::with_conf]
+    pub async fn with_conf_async<S: AsRef<OsStr>>(
+        exe: S,
+        bitcoind: &BitcoinD,
+        conf: &Conf,
+    ) -> Result<Self, Error> {
+        let timeout = conf.start_timeout;
+        wait_until(timeout, Error::BitcoindNotReady, || {
+            Ok(bitcoind_ready(bitcoind))
+        })
+        .await?;
+        let mut launch = Launch::spawn(exe.as_ref(), bitcoind, conf, None)?;
+        wait_until(timeout, Error::SockPathNotExist(Duration::ZERO), || {
+            launch.sock_exists()
+        })
+        .await
+        .map_err(|e| e.since(launch.launched))?;
+        let client = LightningRPC::new(&launch.sock_path);
+        let id = wait_until(timeout, Error::GetInfoSyncing(Duration::ZERO), || {
+            launch.synced_id(&client)
+        })
+        .await
+        .map_err(|e| e.since(launch.launched))?;
+        launch.finish(client, id, conf)
+    }
+
+    /// Async version of [LightningD::wait_for_block]
+    pub async fn wait_for_block_async(&self, height: u64, timeout: Duration) -> Result<(), Error> {
+        wait_until(timeout, Error::timeout("wait_for_block", timeout), || {
+            self.reached_block(height)
+        })
+        .await
+    }
+
+    /// Async version of [LightningD::wait_for_peer]
+    pub async fn wait_for_peer_async(&self, node_id: &str, timeout: Duration) -> Result<(), Error> {
+        wait_until(timeout, Error::timeout("wait_for_peer", timeout), || {
+            self.peer_connected(node_id)
+        })
+        .await
+    }
+
+    /// Async version of [LightningD::wait_for_log_rpc]
+    pub async fn wait_for_log_rpc_async(
+        &self,
+        pattern: &str,
+        timeout: Duration,
+    ) -> Result<LogEntry, Error> {
+        wait_until(timeout, Error::timeout("wait_for_log_rpc", timeout), || {
+            self.log_entry(pattern)
+        })
+        .await
+    }
+
+    /// Async version of [LightningD::wait_for_gossip_channels]
+    pub async fn wait_for_gossip_channels_async(
+        &self,
+        count: usize,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        wait_until(
+            timeout,
+            Error::timeout("wait_for_gossip_channels", timeout),
+            || self.knows_channels(count),
+        )
+        .await
+    }
+
+    /// Async version of [LightningD::wait_for_onchain_funds]
+    pub async fn wait_for_onchain_funds_async(
+        &self,
+        min_sat: u64,
+        timeout: Duration,
+    ) -> Result<u64, Error> {
+        wait_until(
+            timeout,
+            Error::timeout("wait_for_onchain_funds", timeout),
+            || self.onchain_funds(min_sat),
+        )
+        .await
+    }
+
+    /// Async version of [LightningD::wait_for_channel_announced]
+    pub async fn wait_for_channel_announced_async(
+        &self,
+        channel: &ChannelId,
+        timeout: Duration,
+    ) -> Result<String, Error> {
+        wait_until(
+            timeout,
+            Error::timeout("wait_for_channel_announced", timeout),
+            || self.channel_announced(channel),
+        )
+        .await
+    }
+
+    /// Async version of [LightningD::wait_for_htlc_resolution]
+    pub async fn wait_for_htlc_resolution_async(&self, timeout: Duration) -> Result<(), Error> {
+        wait_until(
+            timeout,
+            Error::timeout("wait_for_htlc_resolution", timeout),
+            || self.htlcs_resolved(),
+        )
+        .await
+    }
+
+    /// Async version of [LightningD::wait_all_channels_active]
+    pub async fn wait_all_channels_active_async(&self, timeout: Duration) -> Result<(), Error> {
+        wait(Waiting::reporting(
+            timeout,
+            "wait_all_channels_active",
+            |seen| self.all_channels_active(seen),
+        ))
+        .await
+    }
+
+    /// Async version of [LightningD::wait_channel_state]
+    pub async fn wait_channel_state_async(
+        &self,
+        channel: &ChannelId,
+        state: &str,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        wait(Waiting::reporting(timeout, "wait_channel_state", |seen| {
+            self.channel_in_state(channel, state, seen)
+        }))
+        .await
+    }
+
+    /// Async version of [LightningD::wait_for_peer_feature]
+    pub async fn wait_for_peer_feature_async(
+        &self,
+        node_id: &str,
+        bit: usize,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        wait_until(
+            timeout,
+            Error::timeout("wait_for_peer_feature", timeout),
+            || self.peer_has_feature(node_id, bit),
+        )
+        .await
+    }
+}
@@ -1,18 +1,380 @@
-use std::net::SocketAddrV4;
+use std::{
+    net::{Ipv4Addr, SocketAddrV4},
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::Network;
 
 #[non_exhaustive]
-#[derive(Default)]
 pub struct Conf {
-    /// lightningd command line arguments containing no spaces like `vec!["--rgb=AABBCC", "-regtest"]`
-    /// note that `--lightning-dir=<dir>`, `--network+regtest`
-    /// cannot be used because they are automatically initialized.
+    /// lightningd command line arguments like `vec!["--alias=mynode", "--rgb=AABBCC"]`, appended
+    /// after the options of the other fields. Options automatically initialized, like
+    /// `--lightning-dir`, `--network` and its aliases like `--testnet`, `--rpc-file` or the
+    /// `--bitcoin-rpc*` ones, cannot be used and return [crate::Error::ReservedOption]
     pub args: Vec<String>,
 
     /// if `true` bitcoind log output will not be suppressed
     pub view_stdout: bool,
 
+    /// maximum time waited for each startup step, ie. bitcoind answering, lightningd creating the
+    /// rpc socket and finishing the sync, also used by [crate::LightningD::restart]. Defaults to
+    /// 30 seconds, slow machines may need more
+    pub start_timeout: Duration,
+
+    /// maximum time the helpers waiting internally, like [crate::LightningD::fund_wallet],
+    /// [crate::LightningD::generate] or [crate::LightningD::open_channel], wait for lightningd to
+    /// see the expected state. Defaults to 30 seconds, slow machines, eg. with `valgrind`, may
+    /// need more
+    pub rpc_wait_timeout: Duration,
+
+    /// if `Some` lightningd is launched through the given wrapper command, eg.
+    /// `vec!["timeout".into(), "600".into()]` or `vec!["strace".into(), "-f".into()]`, the
+    /// lightningd executable and its arguments are appended. Note that on drop the wrapper
+    /// process is killed if lightningd doesn't stop
+    pub exec_wrapper: Option<Vec<String>>,
+
+    /// if `true` lightningd is launched through a shell raising the core file size limit, so that
+    /// crashes of lightningd or its subdaemons leave a core file in the network directory,
+    /// reported by [crate::Error::Crashed]. The system core pattern must write plain files
+    pub enable_coredumps: bool,
+
+    /// if `true` lightningd and its subdaemons run under valgrind checking memory errors and
+    /// leaks, writing a log per process in the lightning-dir, summarized by
+    /// [crate::LightningD::valgrind_report]. Requires valgrind in the `PATH`, note that
+    /// lightningd is much slower and may not start within the crate timeouts on slow machines
+    pub valgrind: bool,
+
+    /// directories prepended to the `PATH` of the lightningd process, so that programs looked
+    /// up there, like `bitcoin-cli` used by the bcli plugin, are found even when not in the
+    /// `PATH` of the current process
+    pub extra_path: Vec<PathBuf>,
+
+    /// if `Some` lightningd runs with the given `(uid, gid)`, eg. to test the access to the rpc
+    /// socket from other users together with `rpc_file_mode`. The tests must run as root, or with
+    /// the privileges to change user, and the lightning-dir must be writable by the given user,
+    /// thus a `work_dir` is usually required. Available only on unix
+    #[cfg(unix)]
+    pub run_as: Option<(u32, u32)>,
+
     /// Allows to specify options to open p2p port or connect to the another node
     pub p2p: P2P,
+
+    /// if `Some` passes `--announce-addr-discovered=<true|false>`, enabling or disabling the
+    /// announcement of the public IP address discovered from peers. When `None` lightningd
+    /// default, `auto`, announces it only if no other address is announced
+    pub announce_discovered: Option<bool>,
+
+    /// if `true` passes `--large-channels` allowing channels above 2^24 sat (wumbo), note that
+    /// it must be enabled on both ends of the channel
+    pub large_channels: bool,
+
+    /// if `true` passes `--disable-dns` so that lightningd never resolves DNS names, eg. DNS
+    /// seeds, avoiding lookup delays in environments without network access
+    pub disable_dns: bool,
+
+    /// if `Some` lightningd reaches the bitcoind rpc through an in-crate proxy injecting the given
+    /// delay and failures, to test how lightningd handles a flaky backend. It can't be used
+    /// together with `bitcoin_rpcconnect` or `bitcoin_rpcport`
+    pub bitcoind_proxy: Option<ProxyConfig>,
+
+    /// if `Some` overrides the bitcoind rpc host derived from the given `BitcoinD`, useful when
+    /// bitcoind is reached through a proxy or from another network namespace
+    pub bitcoin_rpcconnect: Option<String>,
+
+    /// if `Some` overrides the bitcoind rpc port derived from the given `BitcoinD`
+    pub bitcoin_rpcport: Option<u16>,
+
+    /// if `Some` overrides the bitcoind rpc user derived from the `BitcoinD` cookie, it must be
+    /// given together with `bitcoin_rpcpassword`
+    pub bitcoin_rpcuser: Option<String>,
+
+    /// if `Some` overrides the bitcoind rpc password derived from the `BitcoinD` cookie, it must be
+    /// given together with `bitcoin_rpcuser`
+    pub bitcoin_rpcpassword: Option<String>,
+
+    /// the network passed as `--network=<network>`, also naming the network directory holding the
+    /// rpc socket. The given `BitcoinD` must run the same chain. Defaults to [Network::Regtest]
+    pub network: Network,
+
+    /// if `true` passes `--ignore-fee-limits=true` so that the feerates proposed by the peer aren't
+    /// checked, allowing extreme force-close fee scenarios. It should be set on both nodes of the
+    /// channel. Dangerous: outside tests it exposes the node to channels with unsafe fees
+    pub ignore_fee_limits: bool,
+
+    /// if `true` passes `--experimental-splicing` enabling the splice RPCs, like
+    /// [crate::LightningD::splice_init], it must be enabled on both ends of the channel
+    pub experimental_splicing: bool,
+
+    /// if `true` passes `--experimental-anchors` so that channels use anchor outputs, allowing
+    /// to bump the fee of the commitment transaction with [crate::LightningD::bump_close_fee].
+    /// Needed only by lightningd versions before v24.08, newer versions use anchors by default
+    /// and reject the option. It must be enabled on both ends of the channel
+    pub experimental_anchors: bool,
+
+    /// if `Some` passes `--min-emergency-msat=<msat>`, the on-chain funds kept in the wallet to
+    /// bump anchor commitments, not available to [crate::LightningD::fund_channel] and the other
+    /// funding helpers (lightningd default is 25000 sat)
+    pub min_emergency_msat: Option<u64>,
+
+    /// if `true` passes `--developer` enabling the `dev-*` options, requires a lightningd binary
+    /// supporting developer mode
+    pub developer: bool,
+
+    /// if `Some` passes `--dev-force-privkey=<hex>` so that the node id is deterministic, requires
+    /// `developer` to be `true`
+    pub dev_force_privkey: Option<String>,
+
+    /// if `Some` the given directory is used as lightning-dir instead of a temporary one, the
+    /// directory is not deleted when the node is dropped. Returns [crate::Error::DirInUse] if
+    /// another lightningd is running in it
+    pub work_dir: Option<PathBuf>,
+
+    /// subdaemons replaced by custom binaries, each passed as `--subdaemon=<name>:<path>`, eg.
+    /// `vec![("channeld".into(), path)]`
+    pub subdaemons: Vec<(String, PathBuf)>,
+
+    /// plugins loaded at startup, each passed as `--plugin=<path>` with the path canonicalized,
+    /// a missing file returns [crate::Error::PluginNotFound]
+    pub plugins: Vec<PathBuf>,
+
+    /// if `Some` passes `--plugin-dir=<path>` so that every executable in the directory is loaded
+    /// at startup, a missing directory returns [crate::Error::PluginNotFound]
+    pub plugin_dir: Option<PathBuf>,
+
+    /// plugins loaded at startup with their options, each passed as `--plugin=<path>` followed
+    /// by `--<name>=<value>` for every option, eg.
+    /// `vec![(path, vec![("myplugin-port".into(), "1234".into())])]`. Paths are checked like
+    /// `plugins`; once started, the plugins are checked to be active and the options to have the
+    /// given values in `listconfigs`
+    pub plugin_with_opts: Vec<(PathBuf, Vec<(String, String)>)>,
+
+    /// plugins loaded at startup, each passed as `--important-plugin=<path>` and checked like
+    /// `plugins`, unlike `plugins` lightningd shuts down if one of them stops
+    pub important_plugins: Vec<PathBuf>,
+
+    /// plugins not loaded at startup, each passed as `--disable-plugin=<name>`, eg. `vec!["pay"]`
+    pub disable_plugins: Vec<String>,
+
+    /// if `Some` passes `--dev-bitcoind-poll=<secs>` so that new blocks are detected faster than
+    /// the default 30 seconds. lightningd has no equivalent non-dev option, thus it requires
+    /// `developer` to be `true`
+    pub bitcoind_poll_secs: Option<u64>,
+
+    /// if `true` passes `--dev-fast-gossip`, gossip is broadcast every few seconds instead of
+    /// every minute so that channels and updates propagate across a test network quickly,
+    /// requires `developer` to be `true`
+    pub dev_fast_gossip: bool,
+
+    /// if `true` passes `--dev-fast-gossip-prune` so that stale channels are pruned from gossip
+    /// after a minute instead of two weeks, requires `developer` to be `true`
+    pub dev_fast_gossip_prune: bool,
+
+    /// if `Some` the directives, like `"-WIRE_COMMITMENT_SIGNED"`, are written one per line in a
+    /// file in the lightning-dir passed with `--dev-disconnect=<file>` to script disconnections,
+    /// requires `developer` to be `true`
+    pub dev_disconnect: Option<Vec<String>>,
+
+    /// if `true` the node stops sending messages to a peer after its first `revoke_and_ack`, so
+    /// that htlcs through it stall while the connection stays up. It's a `0WIRE_REVOKE_AND_ACK`
+    /// directive added to `dev_disconnect`, requires `developer` to be `true`
+    pub dev_black_hole: bool,
+
+    /// if `true` passes `--dev-fast-reconnect` so that a node retries a dropped peer with a
+    /// channel after at most 3 seconds instead of backing off up to 5 minutes. lightningd has no
+    /// option taking a connect or handshake timeout, this is the one shortening the connection
+    /// timing, requires `developer` to be `true`
+    pub connect_timeout_override: bool,
+
+    /// if `Some` passes `--wallet=<dsn>` to choose the wallet database, eg.
+    /// `sqlite3://:memory:` for an in-memory database which is faster but loses all the state
+    /// when the node is restarted
+    pub wallet_dsn: Option<String>,
+
+    /// if `Some` the sqlite wallet database is created at the given path, eg. on a tmpfs, passing
+    /// `--wallet=sqlite3://<path>`. It can't be used together with `wallet_dsn`. lightningd
+    /// doesn't expose the sqlite journal mode, so WAL can't be toggled
+    pub wallet_path: Option<PathBuf>,
+
+    /// if `Some` passes `--bookkeeper-db=<dsn>` so that the bookkeeper plugin uses its own
+    /// database, eg. `sqlite3://:memory:`
+    pub bookkeeper_db: Option<String>,
+
+    /// if `Some` passes `--database-upgrade=<true|false>`, allowing or refusing the migration of
+    /// a database created by an older lightningd, eg. found in a persistent `work_dir`.
+    /// lightningd refuses to start non-release builds on an old database unless it's `true`
+    pub database_upgrade: Option<bool>,
+
+    /// if `Some` passes `--rpc-file=<path>` naming the rpc socket, used as is when absolute,
+    /// otherwise relative to the network directory in the lightning-dir
+    pub rpc_file: Option<PathBuf>,
+
+    /// if `Some` passes `--rpc-file-mode=<octal>` setting the permissions of the rpc socket, eg.
+    /// `0o660`. Once started, on unix, the socket permissions are checked to match
+    pub rpc_file_mode: Option<u32>,
+
+    /// if `Some` passes `--commit-fee=<percent>`, the percentage of the estimated fee requested
+    /// for the peer commitment transactions, making force-close fees deterministic. Must be
+    /// greater than 0 (lightningd default is 100)
+    pub commit_fee_percent: Option<u64>,
+
+    /// if `Some` passes `--commit-feerate-offset=<perkw>`, the feerate added to the estimate when
+    /// this node, as the channel opener, proposes a commitment feerate update. Nodes with
+    /// different offsets negotiate different commitment fees (lightningd default is 5)
+    pub commit_feerate_offset: Option<u32>,
+
+    /// if `Some` passes `--commit-time=<ms>`, the time lightningd waits to batch htlc changes in
+    /// a single commitment update. Lower values make payments complete faster, higher ones
+    /// exercise the batching. Must fit in a `u32` (lightningd default is 10)
+    pub commit_time_ms: Option<u64>,
+
+    /// if `Some` passes `--max-concurrent-htlcs`, the number of HTLCs one channel can handle
+    /// concurrently in each direction. Must be between 1 and 483 (lightningd default is 30)
+    pub max_concurrent_htlcs: Option<u16>,
+
+    /// if `Some` passes `--cltv-delta=<blocks>`, the blocks added to the expiry of the htlcs
+    /// forwarded by this node. Must be at least 1 and not above the max locktime
+    pub cltv_delta: Option<u32>,
+
+    /// if `Some` passes `--cltv-final=<blocks>`, the expiry requested for the htlcs paying the
+    /// invoices of this node. Must be at least 1 and not above the max locktime
+    pub cltv_final: Option<u32>,
+
+    /// if `Some` passes `--max-locktime-blocks=<blocks>`, the maximum blocks funds may be locked
+    /// for in a force-close or by an htlc. Must be at least 1 (lightningd default is 2016),
+    /// deprecated by newer lightningd versions
+    pub max_locktime_blocks: Option<u32>,
+
+    /// custom htlc tlv types accepted by this node when receiving payments, each passed as
+    /// `--accept-htlc-tlv-type=<type>`
+    pub accept_htlc_tlv_types: Vec<u64>,
+
+    /// if `Some` passes `--funding-confirms=<blocks>`, the confirmations required before a
+    /// channel is usable, also mined by [crate::LightningD::confirm_channel]. Must be at least 1
+    /// (lightningd default is 3)
+    pub funding_confirms: Option<u8>,
+
+    /// if `Some` enables the clnrest plugin passing `--clnrest-host=<ip>` and `--clnrest-port`
+    /// with a free port, the plugin serves https with a self-signed certificate. The base url is
+    /// returned by [crate::LightningD::rest_url]
+    pub clnrest_host: Option<Ipv4Addr>,
+
+    /// options of the autoclean plugin, mapping to `--autoclean-*`
+    pub autoclean: Autoclean,
+
+    /// if `Some` passes `--conf=<path>` to layer a config file under the managed options. The file
+    /// must not contain options automatically initialized like `network`, `lightning-dir` or the
+    /// `bitcoin-rpc*` ones, otherwise [crate::Error::ReservedOption] is returned
+    pub config_file: Option<PathBuf>,
+
+    /// if `Some` the given file is copied as `gossip_store` in the network directory before
+    /// startup, so that the node already knows the graph it contains, eg. one saved from
+    /// [crate::LightningD::gossip_store_path] of another node. The format is versioned, lightningd
+    /// discards a store written by an incompatible version and starts with an empty graph. A
+    /// store in a persistent `work_dir` is overwritten
+    pub gossip_store: Option<PathBuf>,
+
+    /// if `false` passes `--log-timestamps=false` so that log lines have no timestamp prefix,
+    /// making the logs of different runs comparable. Defaults to `true`
+    pub log_timestamps: bool,
+}
+
+impl Default for Conf {
+    fn default() -> Self {
+        Conf {
+            args: Default::default(),
+            view_stdout: Default::default(),
+            start_timeout: crate::STARTUP_TIMEOUT,
+            rpc_wait_timeout: crate::RPC_WAIT_TIMEOUT,
+            exec_wrapper: Default::default(),
+            enable_coredumps: Default::default(),
+            valgrind: Default::default(),
+            extra_path: Default::default(),
+            #[cfg(unix)]
+            run_as: Default::default(),
+            p2p: Default::default(),
+            announce_discovered: Default::default(),
+            large_channels: Default::default(),
+            disable_dns: Default::default(),
+            bitcoind_proxy: Default::default(),
+            bitcoin_rpcconnect: Default::default(),
+            bitcoin_rpcport: Default::default(),
+            bitcoin_rpcuser: Default::default(),
+            bitcoin_rpcpassword: Default::default(),
+            network: Network::Regtest,
+            ignore_fee_limits: Default::default(),
+            experimental_splicing: Default::default(),
+            experimental_anchors: Default::default(),
+            min_emergency_msat: Default::default(),
+            developer: Default::default(),
+            dev_force_privkey: Default::default(),
+            work_dir: Default::default(),
+            subdaemons: Default::default(),
+            plugins: Default::default(),
+            plugin_dir: Default::default(),
+            plugin_with_opts: Default::default(),
+            important_plugins: Default::default(),
+            disable_plugins: Default::default(),
+            bitcoind_poll_secs: Default::default(),
+            dev_fast_gossip: Default::default(),
+            dev_fast_gossip_prune: Default::default(),
+            dev_disconnect: Default::default(),
+            dev_black_hole: Default::default(),
+            connect_timeout_override: Default::default(),
+            wallet_dsn: Default::default(),
+            wallet_path: Default::default(),
+            bookkeeper_db: Default::default(),
+            database_upgrade: Default::default(),
+            rpc_file: Default::default(),
+            rpc_file_mode: Default::default(),
+            commit_fee_percent: Default::default(),
+            commit_feerate_offset: Default::default(),
+            commit_time_ms: Default::default(),
+            max_concurrent_htlcs: Default::default(),
+            cltv_delta: Default::default(),
+            cltv_final: Default::default(),
+            max_locktime_blocks: Default::default(),
+            accept_htlc_tlv_types: Default::default(),
+            funding_confirms: Default::default(),
+            clnrest_host: Default::default(),
+            autoclean: Default::default(),
+            config_file: Default::default(),
+            gossip_store: Default::default(),
+            log_timestamps: true,
+        }
+    }
+}
+
+/// Autoclean plugin options, every `Some` value is passed as the corresponding `--autoclean-*`
+/// option, ages are in seconds
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Autoclean {
+    /// how often, in seconds, to run the clean, maps to `--autoclean-cycle`
+    pub cycle: Option<u64>,
+    /// age after which unpaid expired invoices are deleted, maps to
+    /// `--autoclean-expiredinvoices-age`
+    pub expiredinvoices_age: Option<u64>,
+    /// age after which paid invoices are deleted, maps to `--autoclean-paidinvoices-age`
+    pub paidinvoices_age: Option<u64>,
+    /// age after which successful payments are deleted, maps to `--autoclean-succeededpays-age`
+    pub succeededpays_age: Option<u64>,
+    /// age after which failed payments are deleted, maps to `--autoclean-failedpays-age`
+    pub failedpays_age: Option<u64>,
+    /// age after which settled forwards are deleted, maps to
+    /// `--autoclean-succeededforwards-age`
+    pub succeededforwards_age: Option<u64>,
+    /// age after which failed forwards are deleted, maps to `--autoclean-failedforwards-age`
+    pub failedforwards_age: Option<u64>,
+}
+
+/// Faults injected by the proxy of [Conf::bitcoind_proxy]
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct ProxyConfig {
+    /// delay added before forwarding every bitcoind response
+    pub delay: Duration,
+    /// if `Some(n)` every n-th connection is closed without being forwarded, must be greater
+    /// than 0
+    pub drop_every: Option<u32>,
 }
 
 /// Enum to specify p2p settings
@@ -20,6 +382,9 @@ pub struct Conf {
 pub struct P2P {
     pub connect: Option<IdHost>, // available only if the node is listening
     pub listen_announce: ListenAnnounce,
+    /// if `Some` the address to bind or announce when listening, instead of `127.0.0.1` with a
+    /// random free port. Returns [crate::Error::AddrInUse] if it's already taken
+    pub addr: Option<SocketAddrV4>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
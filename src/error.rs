@@ -1,3 +1,5 @@
+use std::time::{Duration, Instant};
+
 #[derive(Debug)]
 pub enum Error {
     /// Wrapper of io Error
@@ -6,11 +8,201 @@ pub enum Error {
     /// Wrapper of rpc client Error
     Rpc(clightningrpc::Error),
 
-    SockPathNotExist,
+    /// Wrapper of bitcoind rpc client Error
+    BitcoinRpc(bitcoind::bitcoincore_rpc::Error),
+
+    /// Wrapper of the Error returned when launching bitcoind
+    BitcoinD(bitcoind::anyhow::Error),
+
+    /// Wrapper of hex parsing Error, eg. of a txid returned by lightningd
+    Hex(bitcoind::bitcoincore_rpc::bitcoin::hashes::hex::HexToArrayError),
+
+    /// Wrapper of address parsing Error
+    Address(bitcoind::bitcoincore_rpc::bitcoin::address::ParseError),
+
+    /// Returned when lightningd doesn't create the rpc socket within
+    /// [crate::Conf::start_timeout], with the time elapsed since it was launched
+    SockPathNotExist(Duration),
+
+    /// Returned by [crate::exe_path] when no `download_*` feature is enabled and the
+    /// `LIGHTNINGD_EXE` env var isn't set
+    NoLightningdExecutableFound,
+
+    /// Returned when lightningd exits during startup, with its exit status. The reason is printed
+    /// by lightningd on stderr
+    StartupFailed(std::process::ExitStatus),
 
-    GetInfoSyncing,
+    /// Returned when lightningd exits leaving a core file, with its exit status and the core path,
+    /// see [crate::Conf::enable_coredumps]
+    Crashed {
+        status: std::process::ExitStatus,
+        core: std::path::PathBuf,
+    },
 
+    /// Returned when lightningd is still syncing with bitcoind after
+    /// [crate::Conf::start_timeout], with the time elapsed since it was launched
+    GetInfoSyncing(Duration),
+
+    /// Returned when bitcoind cookie values are missing or when only one of
+    /// [crate::Conf::bitcoin_rpcuser] and [crate::Conf::bitcoin_rpcpassword] is given
     MissingAuth,
+
+    /// Returned when the given bitcoind doesn't answer RPC calls before lightningd is launched
+    BitcoindNotReady,
+
+    /// Returned when the requested address type isn't supported by the lightningd version
+    AddrTypeUnsupported,
+
+    /// Returned when the peer isn't connected
+    PeerNotConnected,
+
+    /// Returned when the peer node isn't listening for p2p connections, see [crate::P2P]
+    PeerNotListening,
+
+    /// Returned when connecting to a peer fails at the network level, eg. no address is known or
+    /// nothing is listening at the addresses, with the lightningd message
+    PeerUnreachable(String),
+
+    /// Returned when the peer is reached but closes the connection during the handshake, eg.
+    /// because it doesn't accept the connection, with the lightningd message
+    PeerRejected(String),
+
+    /// Returned when the waiting helper `operation`, eg. "wait_for_block", doesn't see the
+    /// expected state after `waited`. Helpers waiting on channels append the last state seen,
+    /// eg. "wait_channel_state, last seen: state CHANNELD_AWAITING_LOCKIN, status [..]"
+    Timeout { operation: String, waited: Duration },
+
+    /// Returned when the contained [crate::Conf] field maps to a `dev-*` option, available only
+    /// when developer mode is enabled
+    DeveloperRequired(&'static str),
+
+    /// Returned when the contained [crate::P2P::addr] is already in use
+    AddrInUse(std::net::SocketAddrV4),
+
+    /// Returned when another lightningd is already running in the given `work_dir`
+    DirInUse,
+
+    /// Returned when this node has no channel with the given id
+    ChannelNotFound,
+
+    /// Returned when the lightningd version doesn't support splicing or it isn't enabled with
+    /// [crate::Conf::experimental_splicing]
+    SpliceUnsupported,
+
+    /// Returned by [crate::LightningD::assert_no_pending] with the description of every pending
+    /// htlc, channel in a transitional state or unconfirmed output
+    Pending(Vec<String>),
+
+    /// Returned by [crate::LightningD::assert_channel_consistent] with the description of every
+    /// mismatch between the two nodes
+    ChannelInconsistent(Vec<String>),
+
+    /// Returned by [crate::LightningD::cooperative_close_and_verify] when the node `node_id`
+    /// receives on-chain an amount out of its channel share minus the closing fee
+    CloseBalanceMismatch {
+        node_id: String,
+        share_msat: u64,
+        received_msat: u64,
+    },
+
+    /// Returned when the peer `channel_update`, needed to build a route hint, isn't received yet
+    RouteHintUnavailable,
+
+    /// Returned when there is no route to the requested destination
+    NoRoute,
+
+    /// Returned when the rpc socket permissions, contained, don't match
+    /// [crate::Conf::rpc_file_mode]
+    RpcFileModeMismatch(u32),
+
+    /// Returned when the contained [crate::Conf] field, or argument of a helper, has a value out
+    /// of the accepted range
+    InvalidOption(&'static str),
+
+    /// Returned when the contained plugin or plugin directory, given with [crate::Conf::plugins],
+    /// [crate::Conf::plugin_with_opts], [crate::Conf::important_plugins] or
+    /// [crate::Conf::plugin_dir], doesn't exist
+    PluginNotFound(std::path::PathBuf),
+
+    /// Returned when the contained plugin, given with [crate::Conf::plugin_with_opts], isn't
+    /// active once lightningd started
+    PluginNotLoaded(std::path::PathBuf),
+
+    /// Returned when the contained option of a [crate::Conf::plugin_with_opts] plugin doesn't
+    /// have the given value in `listconfigs` once lightningd started
+    PluginOptionNotApplied(String),
+
+    /// Returned when the config file or [crate::Conf::args] contain an option automatically
+    /// initialized by the crate
+    ReservedOption(String),
+
+    /// Returned when this node has no payment with the requested payment hash
+    PaymentNotFound,
+
+    /// Returned when lightningd fails a payment, with the failure details if available
+    Payment {
+        failcode: Option<u32>,
+        failcodename: Option<String>,
+        erring_node: Option<String>,
+    },
+}
+
+impl Error {
+    pub(crate) fn timeout(operation: &str, waited: Duration) -> Self {
+        Error::Timeout {
+            operation: operation.to_string(),
+            waited,
+        }
+    }
+
+    /// Appends to the `operation` of a [Error::Timeout] what the waiting helper saw last, eg. the
+    /// state of the channel it waits for
+    pub(crate) fn with_last_seen(self, last: &str) -> Self {
+        match self {
+            Error::Timeout { operation, waited } => Error::Timeout {
+                operation: format!("{}, last seen: {}", operation, last),
+                waited,
+            },
+            e => e,
+        }
+    }
+
+    /// Sets the time elapsed since `launched` in the startup errors carrying it
+    pub(crate) fn since(self, launched: Instant) -> Self {
+        match self {
+            Error::SockPathNotExist(_) => Error::SockPathNotExist(launched.elapsed()),
+            Error::GetInfoSyncing(_) => Error::GetInfoSyncing(launched.elapsed()),
+            e => e,
+        }
+    }
+
+    /// Converts errors of the `connect` RPC, using [Error::PeerUnreachable] for network failures
+    /// (error codes 400 and 401) and [Error::PeerRejected] for peers closing the connection (402)
+    pub(crate) fn from_connect(e: clightningrpc::Error) -> Self {
+        match e {
+            clightningrpc::Error::Rpc(rpc) if rpc.code == 400 || rpc.code == 401 => {
+                Error::PeerUnreachable(rpc.message)
+            }
+            clightningrpc::Error::Rpc(rpc) if rpc.code == 402 => Error::PeerRejected(rpc.message),
+            e => Error::Rpc(e),
+        }
+    }
+
+    /// Converts errors of the `pay` RPC, using [Error::Payment] for payment failures (error codes
+    /// 200-219)
+    pub(crate) fn from_pay(e: clightningrpc::Error) -> Self {
+        match e {
+            clightningrpc::Error::Rpc(rpc) if (200..220).contains(&rpc.code) => {
+                let data = rpc.data.unwrap_or_default();
+                Error::Payment {
+                    failcode: data["failcode"].as_u64().map(|c| c as u32),
+                    failcodename: data["failcodename"].as_str().map(ToString::to_string),
+                    erring_node: data["erring_node"].as_str().map(ToString::to_string),
+                }
+            }
+            e => Error::Rpc(e),
+        }
+    }
 }
 
 impl From<std::io::Error> for Error {
@@ -24,3 +216,27 @@ impl From<clightningrpc::Error> for Error {
         Error::Rpc(e)
     }
 }
+
+impl From<bitcoind::bitcoincore_rpc::Error> for Error {
+    fn from(e: bitcoind::bitcoincore_rpc::Error) -> Self {
+        Error::BitcoinRpc(e)
+    }
+}
+
+impl From<bitcoind::anyhow::Error> for Error {
+    fn from(e: bitcoind::anyhow::Error) -> Self {
+        Error::BitcoinD(e)
+    }
+}
+
+impl From<bitcoind::bitcoincore_rpc::bitcoin::hashes::hex::HexToArrayError> for Error {
+    fn from(e: bitcoind::bitcoincore_rpc::bitcoin::hashes::hex::HexToArrayError) -> Self {
+        Error::Hex(e)
+    }
+}
+
+impl From<bitcoind::bitcoincore_rpc::bitcoin::address::ParseError> for Error {
+    fn from(e: bitcoind::bitcoincore_rpc::bitcoin::address::ParseError) -> Self {
+        Error::Address(e)
+    }
+}
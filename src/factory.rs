@@ -0,0 +1,50 @@
+//! Fixture sharing one bitcoind between many lightningd
+
+use std::ffi::{OsStr, OsString};
+
+use bitcoind::BitcoinD;
+
+use crate::{Conf, Error, LightningD, ListenAnnounce, P2P};
+
+/// Holds a single `BitcoinD` and spawns fresh [LightningD] connected to it, so that the expensive
+/// bitcoind startup is paid once for many tests.
+///
+/// The spawned nodes must not outlive the factory, otherwise their bitcoind is stopped while they
+/// are running
+pub struct LightningFactory {
+    exe: OsString,
+    bitcoind: BitcoinD,
+}
+
+impl LightningFactory {
+    /// Creates a factory spawning lightningd from the given `exe` connected to `bitcoind`
+    pub fn new<S: AsRef<OsStr>>(exe: S, bitcoind: BitcoinD) -> Self {
+        LightningFactory {
+            exe: exe.as_ref().to_owned(),
+            bitcoind,
+        }
+    }
+
+    /// The shared bitcoind, eg. to mine blocks
+    pub fn bitcoind(&self) -> &BitcoinD {
+        &self.bitcoind
+    }
+
+    /// Spawns a lightningd with its own temporary lightning-dir and listening on a free p2p port
+    pub fn spawn(&self) -> Result<LightningD, Error> {
+        let conf = Conf {
+            p2p: P2P {
+                listen_announce: ListenAnnounce::Listen,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        self.spawn_with_conf(&conf)
+    }
+
+    /// Spawns a lightningd with the given [Conf], note that nodes spawned with the same
+    /// [Conf::work_dir] or [P2P::addr] conflict
+    pub fn spawn_with_conf(&self, conf: &Conf) -> Result<LightningD, Error> {
+        LightningD::with_conf(&self.exe, &self.bitcoind, conf)
+    }
+}
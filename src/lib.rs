@@ -1,22 +1,58 @@
 use std::{
-    ffi::OsStr,
+    env,
+    ffi::{OsStr, OsString},
+    fs,
     net::{Ipv4Addr, SocketAddrV4, TcpListener},
+    path::{Path, PathBuf},
     process::{Child, Command, Stdio},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+#[cfg(unix)]
+use std::os::unix::{fs::PermissionsExt, net::UnixStream, process::CommandExt};
+#[cfg(target_os = "linux")]
+use std::sync::atomic::AtomicBool;
+
+use bitcoind::bitcoincore_rpc::bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoind::bitcoincore_rpc::bitcoin::hashes::{sha512, Hash, HashEngine};
+pub use bitcoind::bitcoincore_rpc::bitcoin::Network;
+use bitcoind::bitcoincore_rpc::bitcoin::{Address, Amount, BlockHash, Txid};
+use bitcoind::bitcoincore_rpc::RpcApi;
 use bitcoind::BitcoinD;
+pub use clightningrpc::responses::ListInvoice as Invoice;
+pub use clightningrpc::responses::ListNodesItem as NodeInfo;
+pub use clightningrpc::types::MSat;
+pub use clightningrpc::types::RouteItem as RouteHop;
 use clightningrpc::LightningRPC;
-pub use conf::Conf;
+pub use conf::{Autoclean, Conf, IdHost, ListenAnnounce, ProxyConfig, P2P};
 pub use error::Error;
-use log::debug;
+pub use factory::LightningFactory;
+use log::{debug, warn};
+pub use responses::{
+    BkprAccount, BkprBalance, ChannelAlias, ChannelUpdate, ChannelUpdates, DecodedInvoice,
+    Feerates, Forward, FundPsbt, HistoricalHtlc, HoldInvoice, Htlc, LogEntry, Pay, PeerChannel,
+    SendPay, SendPsbt, Signature, SpliceSigned, SpliceUpdate,
+};
+use serde_json::json;
 use tempfile::TempDir;
 
-use crate::conf::{IdHost, ListenAnnounce};
+use crate::proxy::Proxy;
 
+#[cfg(feature = "async")]
+mod asynchronous;
 mod conf;
 mod error;
+mod factory;
+mod proxy;
+mod responses;
+#[cfg(feature = "download")]
+mod versions;
 
 /// Struct representing the bitcoind process with related information
 pub struct LightningD {
@@ -26,9 +62,169 @@ pub struct LightningD {
     pub client: LightningRPC,
     /// Work directory, where the node store blocks and other stuff. It is kept in the struct so that
     /// directory is deleted only when this struct is dropped
-    _work_dir: TempDir,
+    _work_dir: DataDir,
 
     id_host: Option<IdHost>,
+
+    /// The executable and the arguments used to launch the process
+    command_line: Vec<String>,
+
+    /// Used by [LightningD::restart] to launch the process again
+    sock_path: PathBuf,
+    view_stdout: bool,
+    /// Copied from [Conf::start_timeout]
+    start_timeout: Duration,
+    /// Copied from [Conf::rpc_wait_timeout]
+    rpc_wait_timeout: Duration,
+    /// The `PATH` of the process if changed with [Conf::extra_path]
+    path_env: Option<OsString>,
+    /// The uid and gid of the process, see [Conf::run_as]
+    #[cfg(unix)]
+    run_as: Option<(u32, u32)>,
+    /// Whether the valgrind report is logged on drop, see [Conf::valgrind]
+    valgrind: bool,
+
+    /// Bitcoind wallet address used by [LightningD::generate]
+    mine_address: OnceLock<Address>,
+
+    /// Set by [LightningD::pause] until [LightningD::resume]
+    #[cfg(target_os = "linux")]
+    paused: AtomicBool,
+
+    /// The address the clnrest plugin listens on, see [Conf::clnrest_host]
+    rest_addr: Option<SocketAddrV4>,
+
+    /// Confirmations required before a channel is usable, see [Conf::funding_confirms]
+    funding_confirms: u8,
+
+    /// The network passed as `--network`, returned by [LightningD::network]
+    network: Network,
+
+    /// The proxy to bitcoind given with [Conf::bitcoind_proxy], kept running until this struct is
+    /// dropped
+    _proxy: Option<Proxy>,
+}
+
+/// The node working directory
+enum DataDir {
+    /// Persistent directory given with [Conf::work_dir], it's not deleted on drop
+    Persistent(PathBuf),
+    /// Temporary directory deleted on drop
+    Temporary(TempDir),
+}
+
+impl DataDir {
+    fn path(&self) -> &Path {
+        match self {
+            DataDir::Persistent(path) => path,
+            DataDir::Temporary(tmp_dir) => tmp_dir.path(),
+        }
+    }
+}
+
+/// Type of the on-chain address returned by [LightningD::new_address]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AddrType {
+    #[default]
+    Bech32,
+    /// Not supported by older lightningd versions
+    P2tr,
+}
+
+/// The hex encoded channel id, as in [PeerChannel::channel_id]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChannelId(pub String);
+
+impl From<&str> for ChannelId {
+    fn from(s: &str) -> Self {
+        ChannelId(s.to_string())
+    }
+}
+
+/// A hop of a route hint, from the peer to this node, see [LightningD::route_hint]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RouteHint {
+    /// the peer node id
+    pub id: String,
+    pub short_channel_id: String,
+    pub fee_base_msat: u64,
+    pub fee_proportional_millionths: u32,
+    pub cltv_expiry_delta: u32,
+}
+
+/// Outcome of a payment, see [LightningD::payment_status]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PaymentStatus {
+    Complete,
+    Failed,
+    Pending,
+}
+
+/// Unit of the rates returned by [LightningD::feerates]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FeeStyle {
+    /// satoshi per 1000 weight units
+    #[default]
+    PerKw,
+    /// satoshi per 1000 virtual bytes
+    PerKb,
+}
+
+/// Optional features of the running lightningd, see [LightningD::capabilities]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Capabilities {
+    /// eg. "v24.08"
+    pub version: String,
+    /// developer mode is enabled, so the `dev-*` options are available
+    pub developer: bool,
+    /// the binary supports `--experimental-splicing`
+    pub splicing: bool,
+    /// the clnrest plugin is available
+    pub clnrest: bool,
+}
+
+/// A lightningd with the bitcoind it's connected to, returned by [LightningD::with_bitcoind].
+///
+/// The daemons can't be moved out, so that lightningd is always stopped before bitcoind
+pub struct Daemons {
+    lightningd: LightningD,
+    bitcoind: BitcoinD,
+}
+
+impl Daemons {
+    /// The lightningd connected to [Daemons::bitcoind]
+    pub fn lightningd(&self) -> &LightningD {
+        &self.lightningd
+    }
+
+    /// Like [Daemons::lightningd], mutable to call eg. [LightningD::restart]
+    pub fn lightningd_mut(&mut self) -> &mut LightningD {
+        &mut self.lightningd
+    }
+
+    /// The bitcoind lightningd is connected to
+    pub fn bitcoind(&self) -> &BitcoinD {
+        &self.bitcoind
+    }
+}
+
+impl Drop for Daemons {
+    fn drop(&mut self) {
+        // while bitcoind, dropped afterwards with the fields, is still running
+        let _ = self.lightningd.shutdown();
+    }
+}
+
+/// Summary of the valgrind logs of lightningd and its subdaemons, see
+/// [LightningD::valgrind_report]
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct ValgrindReport {
+    /// the errors reported, including the leaks
+    pub errors: u64,
+    /// the bytes definitely lost
+    pub leaked_bytes: u64,
+    /// the logs with errors or leaks, one per process
+    pub logs: Vec<PathBuf>,
 }
 
 impl LightningD {
@@ -46,192 +242,3831 @@ impl LightningD {
         bitcoind: &BitcoinD,
         conf: &Conf,
     ) -> Result<Self, Error> {
-        let temp_dir = TempDir::new()?;
-        let temp_path = temp_dir.path();
+        Self::start(exe.as_ref(), bitcoind, conf, None)
+    }
 
-        debug!("temp_path: {}", temp_path.display());
+    /// Create a new lightningd process connected with the given bitcoind, with the node secret
+    /// derived from the given BIP39 `mnemonic` (without passphrase), as `lightning-hsmtool
+    /// generatehsm` does.
+    ///
+    /// Useful to test wallet recovery, note the mnemonic checksum is not validated.
+    pub fn from_mnemonic<S: AsRef<OsStr>>(
+        exe: S,
+        bitcoind: &BitcoinD,
+        mnemonic: &str,
+    ) -> Result<Self, Error> {
+        let seed = bip39_seed(mnemonic, "");
+        let mut hsm_secret = [0u8; 32];
+        hsm_secret.copy_from_slice(&seed[..32]);
+        Self::start(exe.as_ref(), bitcoind, &Conf::default(), Some(&hsm_secret))
+    }
 
-        let stdout = if conf.view_stdout {
+    /// Launch a fresh regtest bitcoind from `bitcoind_exe`, mines 100 blocks so that the first
+    /// coinbase is mature, then launch lightningd from `exe` connected to it with default args.
+    ///
+    /// Both are returned in [Daemons], which keeps bitcoind running until lightningd is stopped
+    pub fn with_bitcoind<S: AsRef<OsStr>, T: AsRef<OsStr>>(
+        exe: S,
+        bitcoind_exe: T,
+    ) -> Result<Daemons, Error> {
+        let bitcoind = BitcoinD::new(bitcoind_exe)?;
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)?
+            .assume_checked();
+        bitcoind.client.generate_to_address(100, &address)?;
+        let lightningd = Self::new(exe, &bitcoind)?;
+        Ok(Daemons {
+            lightningd,
+            bitcoind,
+        })
+    }
+
+    fn start(
+        exe: &OsStr,
+        bitcoind: &BitcoinD,
+        conf: &Conf,
+        hsm_secret: Option<&[u8; 32]>,
+    ) -> Result<Self, Error> {
+        let timeout = conf.start_timeout;
+        wait_until(timeout, Error::BitcoindNotReady, || {
+            Ok(bitcoind_ready(bitcoind))
+        })?;
+        let mut launch = Launch::spawn(exe, bitcoind, conf, hsm_secret)?;
+        wait_until(timeout, Error::SockPathNotExist(Duration::ZERO), || {
+            launch.sock_exists()
+        })
+        .map_err(|e| e.since(launch.launched))?;
+        let client = LightningRPC::new(&launch.sock_path);
+        let id = wait_until(timeout, Error::GetInfoSyncing(Duration::ZERO), || {
+            launch.synced_id(&client)
+        })
+        .map_err(|e| e.since(launch.launched))?;
+        launch.finish(client, id, conf)
+    }
+
+    pub fn id_host(&self) -> Option<&IdHost> {
+        self.id_host.as_ref()
+    }
+
+    /// Returns the executable followed by the arguments used to launch lightningd, preceded by
+    /// [Conf::exec_wrapper] if given
+    pub fn command_line(&self) -> &[String] {
+        &self.command_line
+    }
+
+    /// Returns the path of the `gossip_store` file in the lightning-dir
+    pub fn gossip_store_path(&self) -> PathBuf {
+        self.network_dir().join("gossip_store")
+    }
+
+    /// Returns the size in bytes of the `gossip_store` file
+    pub fn gossip_store_size(&self) -> Result<u64, Error> {
+        Ok(fs::metadata(self.gossip_store_path())?.len())
+    }
+
+    /// Stops lightningd with the `stop` rpc and waits until the process exits, killing it if it
+    /// doesn't within [Conf::start_timeout], which also bounds the wait for the rpc answer.
+    /// Unlike dropping the node, errors are returned
+    pub fn stop(mut self) -> Result<(), Error> {
+        self.shutdown()
+    }
+
+    /// Shared by [LightningD::stop], [LightningD::restart] and drop, the process is reaped even
+    /// when the `stop` rpc fails, whose error is returned afterwards
+    fn shutdown(&mut self) -> Result<(), Error> {
+        if self.process.try_wait()?.is_some() {
+            return Ok(());
+        }
+        #[cfg(target_os = "linux")]
+        if self.paused.load(Ordering::SeqCst) {
+            // a stopped process doesn't answer the stop rpc
+            self.resume()?;
+        }
+        let timeout = self.start_timeout;
+        // the default client waits forever for the answer of a wedged lightningd
+        let mut client = LightningRPC::new(&self.sock_path);
+        client.client().set_timeout(Some(timeout));
+        let stop = client.stop();
+        let exited = wait_until(timeout, Error::timeout("stop", timeout), || {
+            Ok(self.process.try_wait()?.map(|_| ()))
+        });
+        if exited.is_err() {
+            self.process.kill()?;
+            self.process.wait()?;
+        }
+        stop?;
+        Ok(())
+    }
+
+    /// Stops lightningd and launches it again with the same command line and lightning-dir,
+    /// waiting until it's ready. The process is killed if it doesn't stop in time.
+    ///
+    /// Peers given with [P2P::connect] aren't connected again. The rpc client is replaced by a new
+    /// one on the same socket, clients built before the restart, like a `LightningRPC` created
+    /// from the socket path, are stale; use [LightningD::client] to get the current one
+    pub fn restart(&mut self) -> Result<(), Error> {
+        self.shutdown()?;
+        let timeout = self.start_timeout;
+        // so that the socket found is the one of the new process
+        let _ = fs::remove_file(&self.sock_path);
+
+        let stdout = if self.view_stdout {
             Stdio::inherit()
         } else {
             Stdio::null()
         };
+        let mut command = Command::new(&self.command_line[0]);
+        if let Some(path) = self.path_env.as_ref() {
+            command.env("PATH", path);
+        }
+        #[cfg(unix)]
+        if let Some((uid, gid)) = self.run_as {
+            command.uid(uid).gid(gid);
+        }
+        self.process = command
+            .args(&self.command_line[1..])
+            .stdout(stdout)
+            .spawn()?;
+        let launched = Instant::now();
+        let network_dir = self.network_dir();
+        wait_until(timeout, Error::SockPathNotExist(Duration::ZERO), || {
+            still_running(&mut self.process, &network_dir)?;
+            Ok(self.sock_path.exists().then_some(()))
+        })
+        .map_err(|e| e.since(launched))?;
+        self.client = LightningRPC::new(&self.sock_path);
+        wait_until(timeout, Error::GetInfoSyncing(Duration::ZERO), || {
+            still_running(&mut self.process, &network_dir)?;
+            Ok(synced_id(&self.client))
+        })
+        .map_err(|e| e.since(launched))?;
+        Ok(())
+    }
 
-        let rpcconnect = format!("--bitcoin-rpcconnect={}", bitcoind.params.rpc_socket.ip());
-        let rpcport = format!("--bitcoin-rpcport={}", bitcoind.params.rpc_socket.port());
+    /// Parses the valgrind logs written in the lightning-dir when launched with
+    /// [Conf::valgrind]. The summary of a process is written when it exits, so it's complete for
+    /// subdaemons already stopped, eg. after [LightningD::restart]
+    pub fn valgrind_report(&self) -> Result<ValgrindReport, Error> {
+        let mut report = ValgrindReport::default();
+        for entry in fs::read_dir(self.work_dir())? {
+            let path = entry?.path();
+            let name = path.file_name().and_then(OsStr::to_str).unwrap_or_default();
+            if !(name.starts_with("valgrind-") && name.ends_with(".log")) {
+                continue;
+            }
+            let (errors, leaked_bytes) = parse_valgrind_log(&fs::read_to_string(&path)?);
+            if errors > 0 || leaked_bytes > 0 {
+                report.errors += errors;
+                report.leaked_bytes += leaked_bytes;
+                report.logs.push(path);
+            }
+        }
+        Ok(report)
+    }
 
-        let cookie = bitcoind
-            .params
-            .get_cookie_values()?
-            .ok_or(Error::MissingAuth)?;
+    /// Freezes the node sending `SIGSTOP` to lightningd and to its subdaemons and plugins, without
+    /// closing its connections, eg. to test ping timeouts on the peer side. The RPC doesn't
+    /// answer until [LightningD::resume] is called.
+    ///
+    /// When launched with [Conf::exec_wrapper] the signals are sent to the wrapper process and
+    /// its children, wrappers not replacing themselves with lightningd, like `strace`, aren't
+    /// supported
+    ///
+    /// A node still paused is resumed before being stopped or dropped. Available only on Linux,
+    /// where the subdaemons and plugins are found through `/proc`
+    #[cfg(target_os = "linux")]
+    pub fn pause(&self) -> Result<(), Error> {
+        self.signal(libc::SIGSTOP)?;
+        self.paused.store(true, Ordering::SeqCst);
+        Ok(())
+    }
 
-        let rpcuser = format!("--bitcoin-rpcuser={}", cookie.user);
-        let rpcpassword = format!("--bitcoin-rpcpassword={}", cookie.password);
+    /// Unfreezes the node paused with [LightningD::pause], sending `SIGCONT`
+    #[cfg(target_os = "linux")]
+    pub fn resume(&self) -> Result<(), Error> {
+        self.signal(libc::SIGCONT)?;
+        self.paused.store(false, Ordering::SeqCst);
+        Ok(())
+    }
 
-        let lightning_dir_arg = format!("--lightning-dir={}", temp_path.display());
+    /// Sends `signal` to lightningd, then to its direct children, which can't spawn others
+    /// meanwhile when the signal is `SIGSTOP`
+    #[cfg(target_os = "linux")]
+    fn signal(&self, signal: libc::c_int) -> Result<(), Error> {
+        let pid = self.process.id();
+        kill(pid, signal)?;
+        for child in child_pids(pid) {
+            // the child may have exited meanwhile
+            let _ = kill(child, signal);
+        }
+        Ok(())
+    }
 
-        let mut p2p_args = vec![];
-        let listen_on = match conf.p2p.listen_announce {
-            ListenAnnounce::No => None,
-            ListenAnnounce::Listen => {
-                let listen_on =
-                    SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), get_available_port()?);
-                p2p_args.push(format!("--bind-addr={}", listen_on));
-                Some(listen_on)
-            }
-            ListenAnnounce::ListenAndAnnounce => {
-                let listen_on =
-                    SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), get_available_port()?);
-                p2p_args.push(format!("--addr={}", listen_on));
-                Some(listen_on)
-            }
-        };
+    /// The rpc client of the running process, the same as the `client` field
+    pub fn client(&self) -> &LightningRPC {
+        &self.client
+    }
 
-        let process = Command::new(exe.as_ref())
-            .arg("--network=regtest")
-            .arg(rpcconnect)
-            .arg(rpcport)
-            .arg(rpcuser)
-            .arg(rpcpassword)
-            .arg(lightning_dir_arg)
-            .args(p2p_args)
-            .stdout(stdout)
-            .spawn()?;
+    /// Returns the lightning-dir of the node, it's available while the node is running even when
+    /// it's a temporary directory deleted on drop
+    pub fn work_dir(&self) -> &Path {
+        self._work_dir.path()
+    }
+
+    fn network_dir(&self) -> PathBuf {
+        network_dir(self._work_dir.path(), self.network)
+    }
 
-        let mut sock_path = temp_path.to_path_buf();
-        sock_path.push("regtest");
-        sock_path.push("lightning-rpc");
+    /// The network the node runs on, see [Conf::network]
+    pub fn network(&self) -> Network {
+        self.network
+    }
 
-        for i in 0.. {
-            if sock_path.exists() {
-                break;
-            } else if i >= 60 {
-                return Err(Error::SockPathNotExist);
+    /// Returns the base url of the clnrest plugin, like `https://127.0.0.1:3010`, if enabled with
+    /// [Conf::clnrest_host]. When bound to `0.0.0.0` the url uses the loopback address
+    pub fn rest_url(&self) -> Option<String> {
+        self.rest_addr.map(|addr| {
+            let ip = if addr.ip().is_unspecified() {
+                Ipv4Addr::LOCALHOST
             } else {
-                thread::sleep(Duration::from_millis(500));
+                *addr.ip()
+            };
+            format!("https://{}:{}", ip, addr.port())
+        })
+    }
+
+    /// Returns the content of the `emergency.recover` file, the static channel backup encrypted
+    /// with the node secret and updated by lightningd at every channel change
+    pub fn export_scb(&self) -> Result<Vec<u8>, Error> {
+        Ok(fs::read(self.network_dir().join("emergency.recover"))?)
+    }
+
+    /// Writes the given static channel backup, as returned by [LightningD::export_scb], as
+    /// `emergency.recover` file and calls `emergencyrecover`, asking the peers to close the
+    /// channels in the backup.
+    ///
+    /// The node must have the same secret of the exporting one, eg. see
+    /// [LightningD::from_mnemonic]
+    pub fn recover_channels(&self, scb: &[u8]) -> Result<(), Error> {
+        fs::write(self.network_dir().join("emergency.recover"), scb)?;
+        let _: serde_json::Value = self.client.call("emergencyrecover", json!({}))?;
+        Ok(())
+    }
+
+    /// Probes `listconfigs` and reports which optional features this lightningd has, so that a
+    /// test can skip early instead of failing with an opaque rpc error. Features not visible in
+    /// the configs, like the postgres wallet backend, aren't reported
+    pub fn capabilities(&self) -> Result<Capabilities, Error> {
+        let version = self.client.getinfo()?.version;
+        let list: serde_json::Value = self.client.call("listconfigs", json!({}))?;
+        // since v23.08 options are nested in `configs`
+        let configs = list
+            .get("configs")
+            .unwrap_or(&list)
+            .as_object()
+            .cloned()
+            .unwrap_or_default();
+        let developer = match configs.get("developer") {
+            Some(developer) => developer["set"].as_bool().unwrap_or(false),
+            None => configs.keys().any(|k| k.starts_with("dev-")),
+        };
+        Ok(Capabilities {
+            version,
+            developer,
+            splicing: configs.contains_key("experimental-splicing"),
+            clnrest: configs.contains_key("clnrest-port"),
+        })
+    }
+
+    /// Returns the `(warning_bitcoind_sync, warning_lightningd_sync)` pair reported by `getinfo`,
+    /// both are `None` when the node is synced
+    pub fn sync_warnings(&self) -> Result<(Option<String>, Option<String>), Error> {
+        let getinfo = self.client.getinfo()?;
+        Ok((
+            getinfo.warning_bitcoind_sync,
+            getinfo.warning_lightningd_sync,
+        ))
+    }
+
+    /// Returns the number of connected peers, from `getinfo`
+    pub fn num_peers(&self) -> Result<u64, Error> {
+        Ok(self.client.getinfo()?.num_peers)
+    }
+
+    /// Returns the number of active channels, from `getinfo`
+    pub fn num_active_channels(&self) -> Result<u64, Error> {
+        Ok(self.client.getinfo()?.num_active_channels)
+    }
+
+    /// Returns a new on-chain address of the node wallet of the given `addr_type`
+    ///
+    /// Returns [Error::AddrTypeUnsupported] if the lightningd version doesn't support it
+    pub fn new_address(&self, addr_type: AddrType) -> Result<String, Error> {
+        let addresstype = match addr_type {
+            AddrType::Bech32 => "bech32",
+            AddrType::P2tr => "p2tr",
+        };
+        let new_addr: responses::NewAddr = match self
+            .client
+            .call("newaddr", json!({ "addresstype": addresstype }))
+        {
+            Err(clightningrpc::Error::Rpc(e)) if e.code == -32602 => {
+                return Err(Error::AddrTypeUnsupported)
             }
+            result => result?,
+        };
+        match addr_type {
+            AddrType::Bech32 => new_addr.bech32,
+            AddrType::P2tr => new_addr.p2tr,
         }
+        .ok_or(Error::AddrTypeUnsupported)
+    }
 
-        let client = LightningRPC::new(&sock_path);
+    /// Sends `amount_sat` from the bitcoind wallet to a new `addr_type` address of this node,
+    /// mines a block and waits until lightningd sees the output confirmed
+    pub fn fund_wallet(
+        &self,
+        bitcoind: &BitcoinD,
+        amount_sat: u64,
+        addr_type: AddrType,
+    ) -> Result<Txid, Error> {
+        let address = Address::from_str(&self.new_address(addr_type)?)?.assume_checked();
+        let txid = bitcoind.client.send_to_address(
+            &address,
+            Amount::from_sat(amount_sat),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )?;
+        self.generate(bitcoind, 1)?;
 
-        let mut i = 0;
-        let id = loop {
-            if let Ok(getinfo) = client.getinfo() {
-                if getinfo.warning_bitcoind_sync.is_none()
-                    && getinfo.warning_lightningd_sync.is_none()
-                {
-                    break getinfo.id;
-                }
-            }
-            if i >= 60 {
-                return Err(Error::GetInfoSyncing);
-            }
-            i += 1;
-            thread::sleep(Duration::from_millis(500));
+        let txid_str = txid.to_string();
+        let timeout = self.rpc_wait_timeout;
+        wait_until(timeout, Error::timeout("fund_wallet", timeout), || {
+            self.funds_confirmed(&txid_str)
+        })?;
+        Ok(txid)
+    }
+
+    /// Like [LightningD::fund_wallet] for many nodes at once: a single bitcoind transaction sends
+    /// `sats_each` to a new [AddrType::Bech32] address of every node, then one block is mined and
+    /// every node waits until it sees its output confirmed.
+    ///
+    /// Returns [Error::InvalidOption] if `nodes` is empty, since there is no transaction to send
+    pub fn fund_all(
+        nodes: &[&LightningD],
+        bitcoind: &BitcoinD,
+        sats_each: u64,
+    ) -> Result<Txid, Error> {
+        let Some(first) = nodes.first() else {
+            return Err(Error::InvalidOption("nodes"));
         };
+        let mut amounts = serde_json::Map::new();
+        for node in nodes {
+            amounts.insert(
+                node.new_address(AddrType::Bech32)?,
+                Amount::from_sat(sats_each).to_btc().into(),
+            );
+        }
+        let txid: Txid = bitcoind
+            .client
+            .call("sendmany", &["".into(), amounts.into()])?;
+        first.generate(bitcoind, 1)?;
 
-        if let Some(IdHost { id, host }) = conf.p2p.connect.as_ref() {
-            let connect_result = client.connect(id, host.map(|h| h.to_string()).as_deref())?;
-            debug!("connect_result: {:?}", connect_result);
+        let txid_str = txid.to_string();
+        for node in nodes {
+            let timeout = node.rpc_wait_timeout;
+            wait_until(timeout, Error::timeout("fund_all", timeout), || {
+                node.funds_confirmed(&txid_str)
+            })?;
         }
+        Ok(txid)
+    }
 
-        let id_host = listen_on.map(|host| IdHost {
-            id,
-            host: Some(host),
-        });
-        Ok(LightningD {
-            process,
-            client,
-            id_host,
-            _work_dir: temp_dir,
-        })
+    /// Waits until the confirmed on-chain balance of this node is at least `min_sat`, eg. once
+    /// the outputs of a close or a sweep return to the wallet, and returns the balance. Returns
+    /// [Error::Timeout] if it isn't after `timeout`
+    pub fn wait_for_onchain_funds(&self, min_sat: u64, timeout: Duration) -> Result<u64, Error> {
+        wait_until(
+            timeout,
+            Error::timeout("wait_for_onchain_funds", timeout),
+            || self.onchain_funds(min_sat),
+        )
     }
 
-    pub fn id_host(&self) -> Option<&IdHost> {
-        self.id_host.as_ref()
+    fn onchain_funds(&self, min_sat: u64) -> Result<Option<u64>, Error> {
+        let balance_sat = self
+            .client
+            .listfunds()?
+            .outputs
+            .iter()
+            .filter(|o| o.status == "confirmed")
+            .map(|o| o.amount_msat.0 / 1000)
+            .sum::<u64>();
+        Ok((balance_sat >= min_sat).then_some(balance_sat))
     }
-}
 
-impl Drop for LightningD {
-    fn drop(&mut self) {
-        let _ = self.client.stop();
-        let _ = self.process.kill();
+    fn funds_confirmed(&self, txid: &str) -> Result<Option<()>, Error> {
+        let funds = self.client.listfunds()?;
+        Ok(funds
+            .outputs
+            .iter()
+            .any(|o| o.txid == txid && o.status == "confirmed")
+            .then_some(()))
     }
-}
 
-/// Returns a non-used local port if available.
-///
-/// Note there is a race condition during the time the method check availability and the caller
-pub fn get_available_port() -> Result<u16, Error> {
-    // using 0 as port let the system assign a port available
-    let t = TcpListener::bind(("127.0.0.1", 0))?; // 0 means the OS choose a free port
-    Ok(t.local_addr().map(|s| s.port())?)
-}
+    /// Returns the feerates lightningd currently uses, in the given `style`
+    pub fn feerates(&self, style: FeeStyle) -> Result<Feerates, Error> {
+        let style_str = match style {
+            FeeStyle::PerKw => "perkw",
+            FeeStyle::PerKb => "perkb",
+        };
+        let feerates: responses::FeeratesByStyle = self
+            .client
+            .call("feerates", json!({ "style": style_str }))?;
+        Ok(match style {
+            FeeStyle::PerKw => feerates.perkw,
+            FeeStyle::PerKb => feerates.perkb,
+        }
+        .unwrap_or_default())
+    }
 
-#[cfg(test)]
-mod tests {
-    use bitcoind::bitcoincore_rpc::RpcApi;
-    use bitcoind::exe_path;
-    use bitcoind::BitcoinD;
-    use log::debug;
-    use log::log_enabled;
-    use log::Level;
+    /// Funds a channel of `amount_sat` with the connected peer `peer_id` using the on-chain wallet
+    /// of this node, pushing `push_msat` to the peer if given so that both sides can pay right
+    /// away. The channel is usable after [LightningD::confirm_channel]
+    pub fn fund_channel(
+        &self,
+        peer_id: &str,
+        amount_sat: u64,
+        push_msat: Option<u64>,
+    ) -> Result<ChannelId, Error> {
+        self.fund_channel_announce(peer_id, amount_sat, push_msat, true)
+    }
 
-    use crate::conf::ListenAnnounce;
-    use crate::conf::P2P;
-    use crate::Conf;
-    use crate::LightningD;
+    /// Like [LightningD::fund_channel] but the channel isn't announced, payments to this node
+    /// over it need the [LightningD::route_hint] of the peer
+    pub fn fund_private_channel(
+        &self,
+        peer_id: &str,
+        amount_sat: u64,
+        push_msat: Option<u64>,
+    ) -> Result<ChannelId, Error> {
+        self.fund_channel_announce(peer_id, amount_sat, push_msat, false)
+    }
 
-    #[test]
-    fn one_lightningd() {
-        let bitcoind = init();
-        let mut conf = Conf::default();
-        conf.view_stdout = log_enabled!(Level::Debug);
-        let exe = std::env::var("LIGHTNINGD_EXE")
-            .expect("LIGHTNINGD_EXE env var pointing to `lightningd` executable is required");
-        let lightningd = LightningD::with_conf(exe, &bitcoind, &conf).unwrap();
-        let getinfo = lightningd.client.getinfo().unwrap();
-        debug!("{:?}", getinfo);
-        assert_eq!(getinfo.blockheight, 100);
+    fn fund_channel_announce(
+        &self,
+        peer_id: &str,
+        amount_sat: u64,
+        push_msat: Option<u64>,
+        announce: bool,
+    ) -> Result<ChannelId, Error> {
+        let mut params = json!({ "id": peer_id, "amount": amount_sat, "announce": announce });
+        if let Some(push_msat) = push_msat {
+            params["push_msat"] = json!(push_msat);
+        }
+        let fund: clightningrpc::responses::FundChannel =
+            self.client.call("fundchannel", params)?;
+        Ok(ChannelId(fund.channel_id))
     }
 
-    #[test]
-    fn two_lightningd() {
-        let bitcoind = init();
+    /// Opens a channel of `amount_sat` with `peer` in one step: funds the wallet of this node from
+    /// `bitcoind`, connects to `peer`, funds the channel pushing `push_msat` if given, like
+    /// [LightningD::fund_channel], and mines the blocks needed until the channel is
+    /// `CHANNELD_NORMAL` on both nodes.
+    ///
+    /// Returns [Error::PeerNotListening] if `peer` doesn't listen for connections and
+    /// [Error::InvalidOption] if `amount_sat` is too big to be funded
+    pub fn open_channel(
+        &self,
+        peer: &LightningD,
+        bitcoind: &BitcoinD,
+        amount_sat: u64,
+        push_msat: Option<u64>,
+    ) -> Result<ChannelId, Error> {
+        self.open_channel_announce(peer, bitcoind, amount_sat, push_msat, true)
+    }
 
-        let exe = std::env::var("LIGHTNINGD_EXE")
-            .expect("LIGHTNINGD_EXE env var pointing to `lightningd` executable is required");
+    /// Like [LightningD::open_channel] but the channel isn't announced, like
+    /// [LightningD::fund_private_channel]
+    pub fn open_private_channel(
+        &self,
+        peer: &LightningD,
+        bitcoind: &BitcoinD,
+        amount_sat: u64,
+        push_msat: Option<u64>,
+    ) -> Result<ChannelId, Error> {
+        self.open_channel_announce(peer, bitcoind, amount_sat, push_msat, false)
+    }
 
-        let mut conf = Conf::default();
-        conf.view_stdout = log_enabled!(Level::Debug);
-        conf.p2p = P2P {
-            connect: None,
-            listen_announce: ListenAnnounce::Listen,
-        };
+    fn open_channel_announce(
+        &self,
+        peer: &LightningD,
+        bitcoind: &BitcoinD,
+        amount_sat: u64,
+        push_msat: Option<u64>,
+        announce: bool,
+    ) -> Result<ChannelId, Error> {
+        let id_host = peer.id_host().ok_or(Error::PeerNotListening)?;
+        let wallet_sat = amount_sat
+            .checked_add(OPEN_CHANNEL_FEE_MARGIN_SAT)
+            .ok_or(Error::InvalidOption("amount_sat"))?;
+        self.fund_wallet(bitcoind, wallet_sat, AddrType::Bech32)?;
+        self.connect_to(id_host)?;
+        let channel = self.fund_channel_announce(&id_host.id, amount_sat, push_msat, announce)?;
+        self.confirm_channel(bitcoind)?;
+        for node in [self, peer] {
+            node.wait_channel_state(&channel, "CHANNELD_NORMAL", node.rpc_wait_timeout)?;
+        }
+        Ok(channel)
+    }
 
-        let lightningd_1 = LightningD::with_conf(&exe, &bitcoind, &conf).unwrap();
-        assert!(lightningd_1.id_host().is_some());
+    /// Selects and reserves wallet inputs for `amount_sat` plus the fee at `feerate`, eg. "normal"
+    /// or "253perkw", for a transaction of `startweight` weight without the inputs. The outputs
+    /// must be added to the returned psbt
+    pub fn fund_psbt(
+        &self,
+        amount_sat: u64,
+        feerate: &str,
+        startweight: u32,
+    ) -> Result<FundPsbt, Error> {
+        Ok(self.client.call(
+            "fundpsbt",
+            json!({ "satoshi": amount_sat, "feerate": feerate, "startweight": startweight }),
+        )?)
+    }
 
-        conf.p2p = P2P {
-            connect: lightningd_1.id_host().cloned(),
-            listen_announce: ListenAnnounce::Listen,
-        };
+    /// Signs the wallet inputs of `psbt`, returns the signed psbt
+    pub fn sign_psbt(&self, psbt: &str) -> Result<String, Error> {
+        let sign: responses::SignPsbt = self.client.call("signpsbt", json!({ "psbt": psbt }))?;
+        Ok(sign.signed_psbt)
+    }
 
-        let lightningd_2 = LightningD::with_conf(&exe, &bitcoind, &conf).unwrap();
-        let list_peers = lightningd_2.client.listpeers(None, None).unwrap();
-        assert_eq!(list_peers.peers.len(), 1);
+    /// Finalizes and broadcasts the signed `psbt`
+    pub fn send_psbt(&self, psbt: &str) -> Result<SendPsbt, Error> {
+        Ok(self.client.call("sendpsbt", json!({ "psbt": psbt }))?)
     }
 
-    fn init() -> BitcoinD {
-        let _ = env_logger::try_init();
-        let bitcoind_exe = exe_path().unwrap();
-        let bitcoind = BitcoinD::new(bitcoind_exe).unwrap();
-        let address = bitcoind
+    /// Returns the route to `dest` node for `msat`, or [Error::NoRoute] if there is none
+    pub fn get_route(&self, dest: &str, msat: u64) -> Result<Vec<RouteHop>, Error> {
+        self.get_route_excluding(dest, msat, &[])
+    }
+
+    /// Like [LightningD::get_route] but the route avoids the `exclude` elements, either node ids
+    /// or channel directions like `103x1x0/1`, eg. to force routing around a hop
+    pub fn get_route_excluding(
+        &self,
+        dest: &str,
+        msat: u64,
+        exclude: &[&str],
+    ) -> Result<Vec<RouteHop>, Error> {
+        // positional params are used because the amount param name changed across versions, nulls
+        // keep the defaults of cltv, fromid and fuzzpercent
+        let params = json!([dest, msat, 1, null, null, null, exclude]);
+        let get_route: Result<clightningrpc::responses::GetRoute, _> =
+            self.client.call("getroute", params);
+        match get_route {
+            Err(clightningrpc::Error::Rpc(e)) if e.code == 205 => Err(Error::NoRoute),
+            result => Ok(result?.route),
+        }
+    }
+
+    /// Pays the given `bolt11` invoice, failures reported by lightningd are returned as
+    /// [Error::Payment]
+    pub fn pay(&self, bolt11: &str) -> Result<clightningrpc::responses::Pay, Error> {
+        self.client
+            .pay(bolt11, Default::default())
+            .map_err(Error::from_pay)
+    }
+
+    /// Creates an invoice of `msat` on `peer` and pays it
+    pub fn pay_to(
+        &self,
+        peer: &LightningD,
+        msat: u64,
+    ) -> Result<clightningrpc::responses::Pay, Error> {
+        let label = format!("pay_to-{}", LABEL_COUNTER.fetch_add(1, Ordering::Relaxed));
+        let invoice = peer
             .client
-            .get_new_address(None, None)
-            .unwrap()
-            .assume_checked();
-        bitcoind.client.generate_to_address(100, &address).unwrap();
-        bitcoind
+            .invoice(Some(msat), &label, "pay_to", None, None, None)?;
+        self.pay(&invoice.bolt11)
+    }
+
+    /// Creates an invoice of `msat` which isn't settled automatically when paid, the HTLC is held
+    /// until [LightningD::settle_hold_invoice] or [LightningD::cancel_hold_invoice] is called.
+    ///
+    /// Lightningd doesn't support hold invoices natively, the
+    /// [holdinvoice](https://github.com/daywalker90/holdinvoice) plugin must be loaded, for
+    /// example with [Conf::plugins]
+    pub fn create_hold_invoice(
+        &self,
+        msat: u64,
+        label: &str,
+        description: &str,
+    ) -> Result<HoldInvoice, Error> {
+        Ok(self.client.call(
+            "holdinvoice",
+            json!({ "amount_msat": msat, "label": label, "description": description }),
+        )?)
+    }
+
+    /// Settles the held invoice with the given `payment_hash`, requires the holdinvoice plugin
+    pub fn settle_hold_invoice(&self, payment_hash: &str) -> Result<(), Error> {
+        let _: serde_json::Value = self
+            .client
+            .call("holdinvoicesettle", json!({ "payment_hash": payment_hash }))?;
+        Ok(())
+    }
+
+    /// Cancels the held invoice with the given `payment_hash`, failing the HTLC back to the payer,
+    /// requires the holdinvoice plugin
+    pub fn cancel_hold_invoice(&self, payment_hash: &str) -> Result<(), Error> {
+        let _: serde_json::Value = self
+            .client
+            .call("holdinvoicecancel", json!({ "payment_hash": payment_hash }))?;
+        Ok(())
+    }
+
+    /// Deletes the payment with `payment_hash` in the given `status`, "complete" or "failed", to
+    /// reset the payment state between sub-tests
+    pub fn del_pay(&self, payment_hash: &str, status: &str) -> Result<(), Error> {
+        let _: serde_json::Value = self.client.call(
+            "delpay",
+            json!({ "payment_hash": payment_hash, "status": status }),
+        )?;
+        Ok(())
+    }
+
+    /// Deletes the invoice with `label` in the given `status`, "paid", "expired" or "unpaid"
+    pub fn del_invoice(&self, label: &str, status: &str) -> Result<(), Error> {
+        self.client.delinvoice(label, status)?;
+        Ok(())
+    }
+
+    /// Returns the htlcs forwarded by this node, from `listforwards`
+    pub fn forwards(&self) -> Result<Vec<Forward>, Error> {
+        let list: responses::ListForwards = self.client.call("listforwards", json!({}))?;
+        Ok(list.forwards)
+    }
+
+    /// Returns the msat collected in fees by the settled forwards
+    pub fn fees_collected(&self) -> Result<u64, Error> {
+        Ok(self
+            .forwards()?
+            .iter()
+            .filter(|f| f.status == "settled")
+            .filter_map(|f| f.fee_msat)
+            .map(|m| m.0)
+            .sum())
+    }
+
+    /// Returns the balances of every account tracked by the bookkeeper plugin, from
+    /// `bkpr-listbalances`
+    pub fn bkpr_listbalances(&self) -> Result<Vec<BkprAccount>, Error> {
+        let list: responses::BkprListBalances = self.client.call("bkpr-listbalances", json!({}))?;
+        Ok(list.accounts)
+    }
+
+    /// Returns the payments made by this node, from `listpays`
+    pub fn pays(&self) -> Result<Vec<Pay>, Error> {
+        let list_pays: responses::ListPays = self.client.call("listpays", json!({}))?;
+        Ok(list_pays.pays)
+    }
+
+    /// Returns the status of the payment of `payment_hash` made by this node, from `listpays`. A
+    /// payment retried after failing is [PaymentStatus::Complete] if any attempt completed.
+    ///
+    /// Returns [Error::PaymentNotFound] if this node never tried to pay it
+    pub fn payment_status(&self, payment_hash: &str) -> Result<PaymentStatus, Error> {
+        let list_pays: responses::ListPays = self
+            .client
+            .call("listpays", json!({ "payment_hash": payment_hash }))?;
+        let has = |status: &str| list_pays.pays.iter().any(|p| p.status == status);
+        if has("complete") {
+            Ok(PaymentStatus::Complete)
+        } else if has("pending") {
+            Ok(PaymentStatus::Pending)
+        } else if has("failed") {
+            Ok(PaymentStatus::Failed)
+        } else {
+            Err(Error::PaymentNotFound)
+        }
+    }
+
+    /// Returns the payment parts sent by this node, from `listsendpays`, optionally only the ones
+    /// of `payment_hash`. Multi-part payments have an entry for every part
+    pub fn send_pays(&self, payment_hash: Option<&str>) -> Result<Vec<SendPay>, Error> {
+        let list: responses::ListSendPays = self
+            .client
+            .call("listsendpays", json!({ "payment_hash": payment_hash }))?;
+        Ok(list.payments)
+    }
+
+    /// Returns the invoices created by this node, from `listinvoices`
+    pub fn invoices(&self) -> Result<Vec<Invoice>, Error> {
+        Ok(self.client.listinvoices(None, None, None, None)?.invoices)
+    }
+
+    /// Mines `blocks` with the given `bitcoind` and waits until lightningd sees the new height.
+    ///
+    /// Blocks are mined to an address of the bitcoind wallet, the same for every call
+    pub fn generate(&self, bitcoind: &BitcoinD, blocks: u64) -> Result<Vec<BlockHash>, Error> {
+        let mine_to = match self.mine_address.get() {
+            Some(address) => address.clone(),
+            None => {
+                let address = bitcoind
+                    .client
+                    .get_new_address(None, None)?
+                    .assume_checked();
+                self.mine_address.get_or_init(|| address).clone()
+            }
+        };
+        let hashes = bitcoind.client.generate_to_address(blocks, &mine_to)?;
+        let height = bitcoind.client.get_block_count()?;
+        self.wait_for_block(height, self.rpc_wait_timeout)?;
+        Ok(hashes)
+    }
+
+    /// Mines the blocks required for a channel just funded to be usable, as many as
+    /// [Conf::funding_confirms]
+    pub fn confirm_channel(&self, bitcoind: &BitcoinD) -> Result<Vec<BlockHash>, Error> {
+        self.generate(bitcoind, self.funding_confirms as u64)
+    }
+
+    /// Simulates a reorg: invalidates the `bitcoind` blocks above `to_height`, mines a longer
+    /// competing chain and waits until lightningd follows it
+    pub fn invalidate_and_resync(&self, bitcoind: &BitcoinD, to_height: u64) -> Result<(), Error> {
+        let old_height = bitcoind.client.get_block_count()?;
+        if to_height < old_height {
+            let hash = bitcoind.client.get_block_hash(to_height + 1)?;
+            bitcoind.client.invalidate_block(&hash)?;
+        }
+        // a fresh address makes sure the competing blocks differ from the invalidated ones
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)?
+            .assume_checked();
+        let blocks = old_height.saturating_sub(to_height) + 1;
+        bitcoind.client.generate_to_address(blocks, &address)?;
+        // the new chain is longer than the old one, reaching its tip means the reorg happened
+        let new_height = bitcoind.client.get_block_count()?;
+        // the competing chain may be long, so twice the usual wait
+        self.wait_for_block(new_height, self.rpc_wait_timeout * 2)
+    }
+
+    /// Waits until lightningd reaches the given block `height`, returns
+    /// [Error::Timeout] if it isn't reached after `timeout`
+    pub fn wait_for_block(&self, height: u64, timeout: Duration) -> Result<(), Error> {
+        wait_until(timeout, Error::timeout("wait_for_block", timeout), || {
+            self.reached_block(height)
+        })
+    }
+
+    /// Like [LightningD::wait_for_block] but waits server side with `waitblockheight`, avoiding
+    /// repeated `getinfo` calls. Returns the reached height or [Error::Timeout]
+    pub fn wait_block_height(&self, height: u64, timeout: Duration) -> Result<u64, Error> {
+        // the timeout is in whole seconds, rounded up so that a sub-second one still waits
+        let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+        let wait: Result<responses::WaitBlockHeight, _> = self.client.call(
+            "waitblockheight",
+            json!({ "blockheight": height, "timeout": secs }),
+        );
+        match wait {
+            // WAIT_TIMEOUT
+            Err(clightningrpc::Error::Rpc(e)) if e.code == 2000 => {
+                Err(Error::timeout("wait_block_height", timeout))
+            }
+            result => Ok(result?.blockheight),
+        }
+    }
+
+    fn reached_block(&self, height: u64) -> Result<Option<()>, Error> {
+        Ok((self.client.getinfo()?.blockheight >= height).then_some(()))
+    }
+
+    /// Returns the channels of this node, from `listpeerchannels` available since lightningd
+    /// v23.02
+    pub fn peer_channels(&self) -> Result<Vec<PeerChannel>, Error> {
+        let list: responses::ListPeerChannels = self.client.call("listpeerchannels", json!({}))?;
+        Ok(list.channels)
+    }
+
+    /// Returns the in-flight htlcs of the given channel, returns [Error::ChannelNotFound] if this
+    /// node has no such channel
+    pub fn htlcs(&self, channel: &ChannelId) -> Result<Vec<Htlc>, Error> {
+        Ok(self.channel(channel)?.htlcs)
+    }
+
+    /// Returns every htlc, in-flight or resolved, of the given channel or of all the channels if
+    /// `None`, from `listhtlcs`. Returns [Error::ChannelNotFound] if this node has no such
+    /// channel, a channel without a short channel id has no htlcs
+    pub fn list_htlcs(&self, channel: Option<&ChannelId>) -> Result<Vec<HistoricalHtlc>, Error> {
+        let params = match channel {
+            Some(channel) => match self.channel(channel)?.short_channel_id {
+                Some(scid) => json!({ "id": scid }),
+                None => return Ok(vec![]),
+            },
+            None => json!({}),
+        };
+        let list: responses::ListHtlcs = self.client.call("listhtlcs", params)?;
+        Ok(list.htlcs)
+    }
+
+    /// Returns the msat this node can send through the given channel, accounting for reserves and
+    /// in-flight htlcs. Returns [Error::ChannelNotFound] if this node has no such channel
+    pub fn spendable_msat(&self, channel: &ChannelId) -> Result<u64, Error> {
+        Ok(self.channel(channel)?.spendable_msat.map_or(0, |m| m.0))
+    }
+
+    /// Returns the msat this node can receive through the given channel, accounting for reserves
+    /// and in-flight htlcs. Returns [Error::ChannelNotFound] if this node has no such channel
+    pub fn receivable_msat(&self, channel: &ChannelId) -> Result<u64, Error> {
+        Ok(self.channel(channel)?.receivable_msat.map_or(0, |m| m.0))
+    }
+
+    /// Returns the funding outpoint of the given channel, to check the funding transaction
+    /// on-chain. Returns [Error::ChannelNotFound] if this node has no such channel or it isn't
+    /// funded yet
+    pub fn channel_funding(&self, channel: &ChannelId) -> Result<(Txid, u32), Error> {
+        let channel = self.channel(channel)?;
+        match (channel.funding_txid, channel.funding_outnum) {
+            (Some(txid), Some(outnum)) => Ok((Txid::from_str(&txid)?, outnum)),
+            _ => Err(Error::ChannelNotFound),
+        }
+    }
+
+    /// Returns the hint to route payments to this node over the given channel, from the
+    /// `channel_update` of the peer. The channel alias is used when the short channel id isn't
+    /// public. Returns [Error::RouteHintUnavailable] if the peer update isn't received yet
+    pub fn route_hint(&self, channel: &ChannelId) -> Result<RouteHint, Error> {
+        let channel = self.channel(channel)?;
+        let update = channel
+            .updates
+            .and_then(|u| u.remote)
+            .ok_or(Error::RouteHintUnavailable)?;
+        let short_channel_id = match (channel.private, channel.alias) {
+            (Some(true), Some(alias)) => alias.remote,
+            _ => None,
+        }
+        .or(channel.short_channel_id)
+        .ok_or(Error::RouteHintUnavailable)?;
+        Ok(RouteHint {
+            id: channel.peer_id,
+            short_channel_id,
+            fee_base_msat: update.fee_base_msat.0,
+            fee_proportional_millionths: update.fee_proportional_millionths,
+            cltv_expiry_delta: update.cltv_expiry_delta,
+        })
+    }
+
+    fn channel(&self, channel: &ChannelId) -> Result<PeerChannel, Error> {
+        self.peer_channels()?
+            .into_iter()
+            .find(|c| c.channel_id.as_deref() == Some(channel.0.as_str()))
+            .ok_or(Error::ChannelNotFound)
+    }
+
+    /// Starts splicing `relative_amount_sat` into the given channel, or out of it if negative,
+    /// returns the psbt to be completed with [LightningD::splice_update].
+    ///
+    /// Returns [Error::SpliceUnsupported] if splicing isn't available
+    pub fn splice_init(
+        &self,
+        channel: &ChannelId,
+        relative_amount_sat: i64,
+        psbt: Option<&str>,
+    ) -> Result<String, Error> {
+        let mut params = json!({ "channel_id": channel.0, "relative_amount": relative_amount_sat });
+        if let Some(psbt) = psbt {
+            params["initialpsbt"] = json!(psbt);
+        }
+        let splice_init: responses::SpliceInit = self.splice_call("splice_init", params)?;
+        Ok(splice_init.psbt)
+    }
+
+    /// Updates the splice of the given channel with the peer, must be called until
+    /// [SpliceUpdate::commitments_secured] is `true`
+    pub fn splice_update(&self, channel: &ChannelId, psbt: &str) -> Result<SpliceUpdate, Error> {
+        self.splice_call(
+            "splice_update",
+            json!({ "channel_id": channel.0, "psbt": psbt }),
+        )
+    }
+
+    /// Signs and broadcasts the splice transaction of the given channel
+    pub fn splice_signed(&self, channel: &ChannelId, psbt: &str) -> Result<SpliceSigned, Error> {
+        self.splice_call(
+            "splice_signed",
+            json!({ "channel_id": channel.0, "psbt": psbt }),
+        )
+    }
+
+    fn splice_call<T: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<T, Error> {
+        match self.client.call(method, params) {
+            // unknown command
+            Err(clightningrpc::Error::Rpc(e)) if e.code == -32601 => Err(Error::SpliceUnsupported),
+            result => Ok(result?),
+        }
+    }
+
+    /// Checks there are no in-flight htlcs, no channels in a transitional state, like
+    /// `CHANNELD_AWAITING_LOCKIN`, `CLOSINGD_COMPLETE` before the close confirms or `ONCHAIN`
+    /// before every output is resolved, and no unconfirmed outputs, like sweeps not yet mined.
+    /// Otherwise returns [Error::Pending] describing them.
+    ///
+    /// Useful at the end of a payment test to catch funds left in limbo
+    pub fn assert_no_pending(&self) -> Result<(), Error> {
+        let mut pending = vec![];
+        for channel in self.peer_channels()? {
+            let id = channel.label();
+            let settled = match channel.state.as_str() {
+                "CHANNELD_NORMAL" => true,
+                // onchaind keeps resolved channels listed for 100 blocks
+                "ONCHAIN" => channel.status.iter().any(|s| s.contains(ONCHAIN_RESOLVED)),
+                _ => false,
+            };
+            if !settled {
+                pending.push(format!("channel {} in state {}", id, channel.state));
+            }
+            for htlc in channel.htlcs.iter() {
+                pending.push(format!(
+                    "htlc {} {} of {} in channel {} in state {}",
+                    htlc.id, htlc.direction, htlc.amount_msat, id, htlc.state
+                ));
+            }
+        }
+        for output in self.client.listfunds()?.outputs {
+            if output.status == "unconfirmed" {
+                pending.push(format!(
+                    "output {}:{} unconfirmed",
+                    output.txid, output.output
+                ));
+            }
+        }
+        if pending.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Pending(pending))
+        }
+    }
+
+    /// Checks this node and `peer` agree on the given channel: same state, capacity and in-flight
+    /// htlcs, seen in opposite directions, and balances adding up to the capacity once the
+    /// in-flight htlcs are counted. Otherwise returns [Error::ChannelInconsistent] describing
+    /// every mismatch.
+    ///
+    /// Both nodes should be idle, updates in progress may show transient differences
+    pub fn assert_channel_consistent(
+        &self,
+        peer: &LightningD,
+        channel: &ChannelId,
+    ) -> Result<(), Error> {
+        let ours = self.channel(channel)?;
+        let theirs = peer.channel(channel)?;
+        let mut mismatches = vec![];
+        if ours.state != theirs.state {
+            mismatches.push(format!("state {} vs {}", ours.state, theirs.state));
+        }
+        if ours.total_msat != theirs.total_msat {
+            mismatches.push(format!(
+                "total_msat {:?} vs {:?}",
+                ours.total_msat, theirs.total_msat
+            ));
+        }
+        for htlc in ours.htlcs.iter() {
+            let opposite = if htlc.direction == "in" { "out" } else { "in" };
+            let mirrored = theirs.htlcs.iter().any(|h| {
+                h.id == htlc.id
+                    && h.direction == opposite
+                    && h.amount_msat == htlc.amount_msat
+                    && h.payment_hash == htlc.payment_hash
+            });
+            if !mirrored {
+                mismatches.push(format!(
+                    "htlc {} {} of {} missing on the peer",
+                    htlc.id, htlc.direction, htlc.amount_msat
+                ));
+            }
+        }
+        if ours.htlcs.len() != theirs.htlcs.len() {
+            mismatches.push(format!(
+                "{} htlcs vs {}",
+                ours.htlcs.len(),
+                theirs.htlcs.len()
+            ));
+        }
+        if let (Some(total), Some(to_us), Some(to_them)) =
+            (ours.total_msat, ours.to_us_msat, theirs.to_us_msat)
+        {
+            let in_flight: u64 = ours.htlcs.iter().map(|h| h.amount_msat.0).sum();
+            let sum = to_us.0 + to_them.0 + in_flight;
+            if sum != total.0 {
+                mismatches.push(format!(
+                    "balances {} + {} + {} in-flight don't add up to {}",
+                    to_us, to_them, in_flight, total
+                ));
+            }
+        }
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::ChannelInconsistent(mismatches))
+        }
+    }
+
+    /// Returns the in-memory log of lightningd, available even without `--log-file`, of the given
+    /// `level` and above, eg. "debug" (lightningd default is "info")
+    pub fn get_log(&self, level: Option<&str>) -> Result<Vec<LogEntry>, Error> {
+        let params = match level {
+            Some(level) => json!({ "level": level }),
+            None => json!({}),
+        };
+        let get_log: responses::GetLog = self.client.call("getlog", params)?;
+        Ok(get_log.log)
+    }
+
+    /// Waits until a debug or higher level entry of the in-memory log contains `pattern`,
+    /// returns [Error::Timeout] if there is none after `timeout`
+    pub fn wait_for_log_rpc(&self, pattern: &str, timeout: Duration) -> Result<LogEntry, Error> {
+        wait_until(timeout, Error::timeout("wait_for_log_rpc", timeout), || {
+            self.log_entry(pattern)
+        })
+    }
+
+    fn log_entry(&self, pattern: &str) -> Result<Option<LogEntry>, Error> {
+        Ok(self
+            .get_log(Some("debug"))?
+            .into_iter()
+            .find(|e| e.log.as_deref().is_some_and(|l| l.contains(pattern))))
+    }
+
+    /// Waits until this node knows at least `count` channels from gossip, note `listchannels`
+    /// lists every channel once per announced direction. Returns [Error::Timeout] if it doesn't
+    /// after `timeout`
+    pub fn wait_for_gossip_channels(&self, count: usize, timeout: Duration) -> Result<(), Error> {
+        wait_until(
+            timeout,
+            Error::timeout("wait_for_gossip_channels", timeout),
+            || self.knows_channels(count),
+        )
+    }
+
+    fn knows_channels(&self, count: usize) -> Result<Option<()>, Error> {
+        let list: responses::ListChannels = self.client.call("listchannels", json!({}))?;
+        Ok((list.channels.len() >= count).then_some(()))
+    }
+
+    /// Cooperatively closes the given channel with `peer`, mines the closing transaction and
+    /// checks that both nodes received on-chain their channel share: exactly, rounded down to the
+    /// sat, for the node that didn't open the channel, less the closing fee for the opener. Shares
+    /// below the dust limit are expected to go to fees.
+    ///
+    /// Returns [Error::CloseBalanceMismatch] if a node received a different amount and
+    /// [Error::Timeout] if a node doesn't see its output within [Conf::rpc_wait_timeout]
+    pub fn cooperative_close_and_verify(
+        &self,
+        peer: &LightningD,
+        channel: &ChannelId,
+        bitcoind: &BitcoinD,
+    ) -> Result<(), Error> {
+        let ours = self.channel(channel)?;
+        let theirs = peer.channel(channel)?;
+        let total_msat = ours.total_msat.ok_or(Error::ChannelNotFound)?.0;
+        let we_opened = ours.opener.as_deref() == Some("local");
+
+        let close: responses::Close = self.client.call("close", json!({ "id": channel.0 }))?;
+        let close_txid = close.txid.ok_or(Error::ChannelNotFound)?;
+        self.generate(bitcoind, 1)?;
+        let height = self.client.getinfo()?.blockheight;
+        peer.wait_for_block(height, peer.rpc_wait_timeout)?;
+
+        let mut received = vec![];
+        let sides = [(self, &ours, we_opened), (peer, &theirs, !we_opened)];
+        for (node, channel, opener) in sides {
+            let share_msat = channel.to_us_msat.map_or(0, |s| s.0);
+            let received_msat = if share_msat / 1000 < DUST_LIMIT_SAT {
+                node.close_output_msat(&close_txid)?
+            } else {
+                // the output may not be in the wallet yet
+                let timeout = node.rpc_wait_timeout;
+                let error = Error::timeout("cooperative_close_and_verify", timeout);
+                wait_until(timeout, error, || {
+                    let msat = node.close_output_msat(&close_txid)?;
+                    Ok((msat > 0).then_some(msat))
+                })?
+            };
+            received.push((node, opener, share_msat, received_msat));
+        }
+        let fee_msat = total_msat.saturating_sub(received.iter().map(|r| r.3).sum());
+        for (node, opener, share_msat, received_msat) in received {
+            let matching = if opener {
+                received_msat <= share_msat && received_msat + fee_msat >= share_msat
+            } else if share_msat / 1000 < DUST_LIMIT_SAT {
+                received_msat == 0
+            } else {
+                received_msat == share_msat / 1000 * 1000
+            };
+            if !matching {
+                return Err(Error::CloseBalanceMismatch {
+                    node_id: node.client.getinfo()?.id,
+                    share_msat,
+                    received_msat,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The amount of the confirmed outputs of the transaction `txid` in the wallet of this node
+    fn close_output_msat(&self, txid: &str) -> Result<u64, Error> {
+        Ok(self
+            .client
+            .listfunds()?
+            .outputs
+            .iter()
+            .filter(|o| o.txid == txid && o.status == "confirmed")
+            .map(|o| o.amount_msat.0)
+            .sum())
+    }
+
+    /// Unilaterally closes the given anchor channel and waits until lightningd broadcasts the
+    /// child transaction spending the anchor output of the commitment, bumping its fee. Returns
+    /// the txid of the child, the commitment is left unconfirmed in the mempool of `bitcoind`.
+    ///
+    /// The channel is failed with `dev-fail` in developer mode, otherwise the peer is force
+    /// disconnected before closing, so that it can't agree on a mutual close meanwhile.
+    ///
+    /// Returns [Error::ChannelNotFound] if there is no funded channel with the given id and
+    /// [Error::Timeout] if the commitment isn't bumped within [Conf::rpc_wait_timeout], eg.
+    /// because the channel doesn't have anchors, see [Conf::experimental_anchors]
+    pub fn bump_close_fee(&self, channel: &ChannelId, bitcoind: &BitcoinD) -> Result<Txid, Error> {
+        let (funding_txid, funding_vout) = self.channel_funding(channel)?;
+        let peer_channel = self.channel(channel)?;
+        if self.capabilities()?.developer {
+            let _: serde_json::Value = self
+                .client
+                .call("dev-fail", json!({ "id": peer_channel.peer_id }))?;
+        } else {
+            if peer_channel.peer_connected {
+                let _: serde_json::Value = self.client.call(
+                    "disconnect",
+                    json!({ "id": peer_channel.peer_id, "force": true }),
+                )?;
+            }
+            let _: responses::Close = self
+                .client
+                .call("close", json!({ "id": channel.0, "unilateraltimeout": 1 }))?;
+        }
+
+        let timeout = self.rpc_wait_timeout;
+        let mut commitment = None;
+        wait_until(timeout, Error::timeout("bump_close_fee", timeout), || {
+            let mempool = bitcoind.client.get_raw_mempool()?;
+            if commitment.is_none() {
+                for txid in mempool.iter() {
+                    let tx = bitcoind.client.get_raw_transaction(txid, None)?;
+                    if tx.input.iter().any(|input| {
+                        input.previous_output.txid == funding_txid
+                            && input.previous_output.vout == funding_vout
+                    }) {
+                        commitment = Some(*txid);
+                    }
+                }
+            }
+            let Some(commitment) = commitment else {
+                return Ok(None);
+            };
+            for txid in mempool {
+                let entry = bitcoind.client.get_mempool_entry(&txid)?;
+                if entry.depends.contains(&commitment) {
+                    return Ok(Some(txid));
+                }
+            }
+            Ok(None)
+        })
+    }
+
+    /// Returns the `status` of the given channel, lightningd messages describing its progress,
+    /// eg. why it isn't advancing to the next state
+    pub fn channel_status(&self, channel: &ChannelId) -> Result<Vec<String>, Error> {
+        Ok(self.channel(channel)?.status)
+    }
+
+    /// Waits until the given channel is in `state`, eg. "CHANNELD_NORMAL" or "ONCHAIN". Returns
+    /// [Error::Timeout] with the last state and status seen if it isn't after `timeout`
+    pub fn wait_channel_state(
+        &self,
+        channel: &ChannelId,
+        state: &str,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        wait(Waiting::reporting(timeout, "wait_channel_state", |seen| {
+            self.channel_in_state(channel, state, seen)
+        }))
+    }
+
+    fn channel_in_state(
+        &self,
+        channel: &ChannelId,
+        state: &str,
+        seen: &mut String,
+    ) -> Result<Option<()>, Error> {
+        let peer_channel = self.channel(channel)?;
+        *seen = format!(
+            "state {}, status {:?}",
+            peer_channel.state, peer_channel.status
+        );
+        Ok((peer_channel.state == state).then_some(()))
+    }
+
+    /// Waits until the given channel of this node is in its own `listchannels` as public, meaning
+    /// it's announced and routable by other nodes, and returns its short channel id. A channel is
+    /// announced only after 6 confirmations and never if private. Returns [Error::Timeout] if it
+    /// isn't announced after `timeout`
+    pub fn wait_for_channel_announced(
+        &self,
+        channel: &ChannelId,
+        timeout: Duration,
+    ) -> Result<String, Error> {
+        wait_until(
+            timeout,
+            Error::timeout("wait_for_channel_announced", timeout),
+            || self.channel_announced(channel),
+        )
+    }
+
+    fn channel_announced(&self, channel: &ChannelId) -> Result<Option<String>, Error> {
+        let Some(scid) = self.channel(channel)?.short_channel_id else {
+            return Ok(None);
+        };
+        let list: responses::ListGossipChannels = self
+            .client
+            .call("listchannels", json!({ "short_channel_id": scid }))?;
+        Ok(list
+            .channels
+            .iter()
+            .any(|c| c.public && c.short_channel_id == scid)
+            .then_some(scid))
+    }
+
+    /// Waits until no channel of this node has in-flight htlcs, eg. before asserting on balances
+    /// after a payment, returns [Error::Timeout] if some remain after `timeout`
+    pub fn wait_for_htlc_resolution(&self, timeout: Duration) -> Result<(), Error> {
+        wait_until(
+            timeout,
+            Error::timeout("wait_for_htlc_resolution", timeout),
+            || self.htlcs_resolved(),
+        )
+    }
+
+    fn htlcs_resolved(&self) -> Result<Option<()>, Error> {
+        Ok(self
+            .peer_channels()?
+            .iter()
+            .all(|c| c.htlcs.is_empty())
+            .then_some(()))
+    }
+
+    /// Waits until every channel of this node is in the `CHANNELD_NORMAL` state, returns
+    /// [Error::Timeout] listing the other channels if they aren't after `timeout`
+    pub fn wait_all_channels_active(&self, timeout: Duration) -> Result<(), Error> {
+        wait(Waiting::reporting(
+            timeout,
+            "wait_all_channels_active",
+            |seen| self.all_channels_active(seen),
+        ))
+    }
+
+    fn all_channels_active(&self, seen: &mut String) -> Result<Option<()>, Error> {
+        let inactive = self.inactive_channels()?;
+        *seen = inactive.join(", ");
+        Ok(inactive.is_empty().then_some(()))
+    }
+
+    fn inactive_channels(&self) -> Result<Vec<String>, Error> {
+        Ok(self
+            .peer_channels()?
+            .iter()
+            .filter(|c| c.state != "CHANNELD_NORMAL")
+            .map(|c| format!("channel {} in state {}", c.label(), c.state))
+            .collect())
+    }
+
+    /// Returns the sum of the `total_msat` of all the channels of this node
+    pub fn total_capacity(&self) -> Result<u64, Error> {
+        Ok(self
+            .peer_channels()?
+            .iter()
+            .filter_map(|c| c.total_msat)
+            .map(|m| m.0)
+            .sum())
+    }
+
+    /// Returns the nodes known through gossip, from `listnodes`
+    pub fn nodes(&self) -> Result<Vec<NodeInfo>, Error> {
+        Ok(self.client.listnodes(None)?.nodes)
+    }
+
+    /// Returns the alias of the node `node_id` as seen by this node, `None` if the node or its
+    /// announcement hasn't been received yet
+    pub fn node_alias(&self, node_id: &str) -> Result<Option<String>, Error> {
+        Ok(self
+            .client
+            .listnodes(Some(node_id))?
+            .nodes
+            .into_iter()
+            .next()
+            .and_then(|n| n.alias))
+    }
+
+    /// Signs `msg` with the node key
+    pub fn sign_message(&self, msg: &str) -> Result<Signature, Error> {
+        Ok(self.client.call("signmessage", json!({ "message": msg }))?)
+    }
+
+    /// Checks the zbase encoded signature `sig` of `msg`, as in [Signature::zbase]. If `node_id` is
+    /// `None` the signer must be a node known by this node
+    pub fn check_message(
+        &self,
+        msg: &str,
+        sig: &str,
+        node_id: Option<&str>,
+    ) -> Result<bool, Error> {
+        let mut params = json!({ "message": msg, "zbase": sig });
+        if let Some(node_id) = node_id {
+            params["pubkey"] = json!(node_id);
+        }
+        let check: responses::CheckMessage = self.client.call("checkmessage", params)?;
+        Ok(check.verified)
+    }
+
+    /// Decodes the given bolt11 or bolt12 string
+    pub fn decode(&self, bolt: &str) -> Result<DecodedInvoice, Error> {
+        Ok(self.client.call("decode", json!({ "string": bolt }))?)
+    }
+
+    /// Pings the connected peer `peer_node_id` and returns the length of the pong received,
+    /// returns [Error::PeerNotConnected] if the peer isn't connected
+    pub fn ping(&self, peer_node_id: &str) -> Result<u32, Error> {
+        self.peer_connected(peer_node_id)?
+            .ok_or(Error::PeerNotConnected)?;
+        Ok(self.client.ping(peer_node_id, None, None)?.totlen as u32)
+    }
+
+    /// Returns the hex encoded features negotiated with peer `node_id`, as reported by `listpeers`
+    pub fn peer_features(&self, node_id: &str) -> Result<String, Error> {
+        let list_peers: responses::ListPeers =
+            self.client.call("listpeers", json!({ "id": node_id }))?;
+        let peer = list_peers
+            .peers
+            .into_iter()
+            .next()
+            .ok_or(Error::PeerNotConnected)?;
+        Ok(peer.features.unwrap_or_default())
+    }
+
+    /// Waits until feature `bit` is set in the features negotiated with peer `node_id`, returns
+    /// [Error::Timeout] if it isn't after `timeout`.
+    ///
+    /// Note both the compulsory (even) and the optional (odd) bit may need to be checked
+    pub fn wait_for_peer_feature(
+        &self,
+        node_id: &str,
+        bit: usize,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        wait_until(
+            timeout,
+            Error::timeout("wait_for_peer_feature", timeout),
+            || self.peer_has_feature(node_id, bit),
+        )
+    }
+
+    fn peer_has_feature(&self, node_id: &str, bit: usize) -> Result<Option<()>, Error> {
+        match self.peer_features(node_id) {
+            Ok(features) => Ok(has_feature_bit(&features, bit).then_some(())),
+            Err(Error::PeerNotConnected) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Connects to the given peer, returns [Error::PeerUnreachable] if it can't be reached and
+    /// [Error::PeerRejected] if it closes the connection
+    pub fn connect_to(&self, peer: &IdHost) -> Result<(), Error> {
+        self.client
+            .connect(&peer.id, peer.host.map(|h| h.to_string()).as_deref())
+            .map_err(Error::from_connect)?;
+        Ok(())
+    }
+
+    /// Force disconnects from the listening `peer` then connects again, waiting until the peer is
+    /// listed as connected, to exercise the reconnection and channel re-establishment paths.
+    ///
+    /// Returns [Error::PeerNotListening] if `peer` doesn't listen for connections
+    pub fn reconnect(&self, peer: &LightningD) -> Result<(), Error> {
+        let IdHost { id, host } = peer.id_host().ok_or(Error::PeerNotListening)?;
+        let _: serde_json::Value = self
+            .client
+            .call("disconnect", json!({ "id": id, "force": true }))?;
+        self.client
+            .connect(id, host.map(|h| h.to_string()).as_deref())
+            .map_err(Error::from_connect)?;
+        self.wait_for_peer(id, self.rpc_wait_timeout)
+    }
+
+    /// Restarts this node, reconnects to `peer` and checks every channel active before the restart
+    /// is back to `CHANNELD_NORMAL`, otherwise returns [Error::Timeout] listing the others.
+    ///
+    /// Returns [Error::PeerNotListening] if `peer` doesn't listen for connections
+    pub fn verify_channels_survive_restart(&mut self, peer: &LightningD) -> Result<(), Error> {
+        let active: Vec<String> = self
+            .peer_channels()?
+            .into_iter()
+            .filter(|c| c.state == "CHANNELD_NORMAL")
+            .filter_map(|c| c.channel_id)
+            .collect();
+        self.restart()?;
+        // lightningd may have reconnected on its own already, connecting again is a no-op
+        let id_host = peer.id_host().ok_or(Error::PeerNotListening)?;
+        self.connect_to(id_host)?;
+        self.wait_for_peer(&id_host.id, self.rpc_wait_timeout)?;
+
+        let timeout = self.rpc_wait_timeout;
+        let operation = "verify_channels_survive_restart";
+        wait(Waiting::reporting(timeout, operation, |seen| {
+            let channels = self.peer_channels()?;
+            let inactive: Vec<String> = active
+                .iter()
+                .filter(|id| {
+                    !channels
+                        .iter()
+                        .any(|c| c.channel_id.as_ref() == Some(id) && c.state == "CHANNELD_NORMAL")
+                })
+                .map(|id| format!("channel {} not active", id))
+                .collect();
+            *seen = inactive.join(", ");
+            Ok(inactive.is_empty().then_some(()))
+        }))
+    }
+
+    /// Waits until the peer with the given `node_id` is listed as connected, returns
+    /// [Error::Timeout] if it isn't after `timeout`
+    pub fn wait_for_peer(&self, node_id: &str, timeout: Duration) -> Result<(), Error> {
+        wait_until(timeout, Error::timeout("wait_for_peer", timeout), || {
+            self.peer_connected(node_id)
+        })
+    }
+
+    fn peer_connected(&self, node_id: &str) -> Result<Option<()>, Error> {
+        let list_peers: responses::ListPeers =
+            self.client.call("listpeers", json!({ "id": node_id }))?;
+        Ok(list_peers.peers.iter().any(|p| p.connected).then_some(()))
+    }
+}
+
+impl Drop for LightningD {
+    fn drop(&mut self) {
+        // reaped before the lightning-dir is deleted
+        if let Err(e) = self.shutdown() {
+            debug!("shutdown failed: {:?}", e);
+        }
+        if self.valgrind {
+            // valgrind writes the summaries once the processes exit
+            match self.valgrind_report() {
+                Ok(report) if report != ValgrindReport::default() => warn!("{:?}", report),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// A launched lightningd process, not yet ready to accept rpc calls. If startup fails the process
+/// is killed and reaped when this is dropped, before the lightning-dir is deleted
+struct Launch {
+    process: Spawned,
+    sock_path: PathBuf,
+    work_dir: DataDir,
+    listen_on: Option<SocketAddrV4>,
+    rest_addr: Option<SocketAddrV4>,
+    command_line: Vec<String>,
+    path_env: Option<OsString>,
+    proxy: Option<Proxy>,
+    network: Network,
+    /// When the process was spawned, to report the startup time in errors
+    launched: Instant,
+}
+
+/// The lightningd process owned by a [Launch], killed on drop unless [Launch::finish] takes it
+struct Spawned(Option<Child>);
+
+impl Spawned {
+    fn child(&mut self) -> &mut Child {
+        self.0.as_mut().expect("taken only by finish")
+    }
+
+    fn take(mut self) -> Child {
+        self.0.take().expect("taken only by finish")
+    }
+}
+
+impl Drop for Spawned {
+    fn drop(&mut self) {
+        if let Some(child) = self.0.as_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+impl Launch {
+    /// Builds the command line from the given [Conf] and spawns the lightningd process, if
+    /// `hsm_secret` is given it's written in the lightning-dir before
+    fn spawn(
+        exe: &OsStr,
+        bitcoind: &BitcoinD,
+        conf: &Conf,
+        hsm_secret: Option<&[u8; 32]>,
+    ) -> Result<Self, Error> {
+        // checked before writing in the work_dir, which may be persistent, or starting the proxy
+        validate(conf)?;
+        let canonical_plugins = |plugins: &[PathBuf]| -> Result<Vec<PathBuf>, Error> {
+            plugins.iter().map(|p| canonical_plugin(p)).collect()
+        };
+        let plugins = canonical_plugins(&conf.plugins)?;
+        let important_plugins = canonical_plugins(&conf.important_plugins)?;
+        let plugin_with_opts = conf
+            .plugin_with_opts
+            .iter()
+            .map(|(plugin, opts)| Ok((canonical_plugin(plugin)?, opts)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let plugin_dir = match conf.plugin_dir.as_ref() {
+            Some(plugin_dir) => {
+                let plugin_dir = canonical_plugin(plugin_dir)?;
+                if !plugin_dir.is_dir() {
+                    return Err(Error::PluginNotFound(plugin_dir));
+                }
+                Some(plugin_dir)
+            }
+            None => None,
+        };
+
+        let work_dir = match conf.work_dir.as_ref() {
+            Some(path) => {
+                fs::create_dir_all(path)?;
+                DataDir::Persistent(path.to_owned())
+            }
+            None => DataDir::Temporary(TempDir::new()?),
+        };
+        let temp_path = work_dir.path().to_path_buf();
+
+        debug!("temp_path: {}", temp_path.display());
+
+        let sock_path = rpc_sock_path(&temp_path, conf.network, conf.rpc_file.as_deref());
+
+        #[cfg(unix)]
+        if UnixStream::connect(&sock_path).is_ok() {
+            return Err(Error::DirInUse);
+        }
+
+        if let Some(hsm_secret) = hsm_secret {
+            let network_dir = network_dir(&temp_path, conf.network);
+            fs::create_dir_all(&network_dir)?;
+            fs::write(network_dir.join("hsm_secret"), hsm_secret)?;
+        }
+        if let Some(gossip_store) = conf.gossip_store.as_ref() {
+            let network_dir = network_dir(&temp_path, conf.network);
+            fs::create_dir_all(&network_dir)?;
+            fs::copy(gossip_store, network_dir.join("gossip_store"))?;
+        }
+
+        let stdout = if conf.view_stdout {
+            Stdio::inherit()
+        } else {
+            Stdio::null()
+        };
+
+        let proxy = match conf.bitcoind_proxy.as_ref() {
+            Some(config) => Some(Proxy::start(bitcoind.params.rpc_socket, config.clone())?),
+            None => None,
+        };
+        let rpc_socket = proxy
+            .as_ref()
+            .map_or(bitcoind.params.rpc_socket, Proxy::addr);
+
+        let rpcconnect = match conf.bitcoin_rpcconnect.as_ref() {
+            Some(host) => format!("--bitcoin-rpcconnect={}", host),
+            None => format!("--bitcoin-rpcconnect={}", rpc_socket.ip()),
+        };
+        let rpcport = format!(
+            "--bitcoin-rpcport={}",
+            conf.bitcoin_rpcport.unwrap_or_else(|| rpc_socket.port())
+        );
+
+        let (user, password) = match (
+            conf.bitcoin_rpcuser.as_ref(),
+            conf.bitcoin_rpcpassword.as_ref(),
+        ) {
+            (Some(user), Some(password)) => (user.clone(), password.clone()),
+            (None, None) => {
+                let cookie = bitcoind
+                    .params
+                    .get_cookie_values()?
+                    .ok_or(Error::MissingAuth)?;
+                (cookie.user, cookie.password)
+            }
+            _ => return Err(Error::MissingAuth),
+        };
+
+        let rpcuser = format!("--bitcoin-rpcuser={}", user);
+        let rpcpassword = format!("--bitcoin-rpcpassword={}", password);
+
+        let lightning_dir_arg = format!("--lightning-dir={}", temp_path.display());
+
+        let mut p2p_args = vec![];
+        let listen_on = match conf.p2p.listen_announce {
+            ListenAnnounce::No => None,
+            ListenAnnounce::Listen => {
+                let listen_on = listen_addr(&conf.p2p)?;
+                p2p_args.push(format!("--bind-addr={}", listen_on));
+                Some(listen_on)
+            }
+            ListenAnnounce::ListenAndAnnounce => {
+                let listen_on = listen_addr(&conf.p2p)?;
+                p2p_args.push(format!("--addr={}", listen_on));
+                Some(listen_on)
+            }
+        };
+
+        let mut conf_args = vec![];
+        if let Some(discovered) = conf.announce_discovered {
+            conf_args.push(format!("--announce-addr-discovered={}", discovered));
+        }
+        if conf.large_channels {
+            conf_args.push("--large-channels".to_string());
+        }
+        if conf.disable_dns {
+            conf_args.push("--disable-dns".to_string());
+        }
+        if !conf.log_timestamps {
+            conf_args.push("--log-timestamps=false".to_string());
+        }
+        if conf.ignore_fee_limits {
+            conf_args.push("--ignore-fee-limits=true".to_string());
+        }
+        if conf.experimental_anchors {
+            conf_args.push("--experimental-anchors".to_string());
+        }
+        if let Some(msat) = conf.min_emergency_msat {
+            conf_args.push(format!("--min-emergency-msat={}", msat));
+        }
+        if conf.experimental_splicing {
+            conf_args.push("--experimental-splicing".to_string());
+        }
+        if conf.developer {
+            conf_args.push("--developer".to_string());
+        }
+        if let Some(privkey) = conf.dev_force_privkey.as_ref() {
+            conf_args.push(format!("--dev-force-privkey={}", privkey));
+        }
+        if let Some(secs) = conf.bitcoind_poll_secs {
+            conf_args.push(format!("--dev-bitcoind-poll={}", secs));
+        }
+        if conf.dev_fast_gossip {
+            conf_args.push("--dev-fast-gossip".to_string());
+        }
+        if conf.dev_fast_gossip_prune {
+            conf_args.push("--dev-fast-gossip-prune".to_string());
+        }
+        if conf.connect_timeout_override {
+            conf_args.push("--dev-fast-reconnect".to_string());
+        }
+        if conf.dev_disconnect.is_some() || conf.dev_black_hole {
+            let mut directives = conf.dev_disconnect.clone().unwrap_or_default();
+            if conf.dev_black_hole {
+                // `0` stops sending anything to the peer after the given message
+                directives.push("0WIRE_REVOKE_AND_ACK".to_string());
+            }
+            let dev_disconnect_path = temp_path.join("dev_disconnect");
+            fs::write(&dev_disconnect_path, directives.join("\n"))?;
+            conf_args.push(format!(
+                "--dev-disconnect={}",
+                dev_disconnect_path.display()
+            ));
+        }
+        for (name, path) in conf.subdaemons.iter() {
+            conf_args.push(format!("--subdaemon={}:{}", name, path.display()));
+        }
+        for plugin in plugins.iter() {
+            conf_args.push(format!("--plugin={}", plugin.display()));
+        }
+        if let Some(plugin_dir) = plugin_dir {
+            conf_args.push(format!("--plugin-dir={}", plugin_dir.display()));
+        }
+        for (plugin, opts) in plugin_with_opts {
+            conf_args.push(format!("--plugin={}", plugin.display()));
+            for (name, value) in opts {
+                conf_args.push(format!("--{}={}", name, value));
+            }
+        }
+        for plugin in important_plugins.iter() {
+            conf_args.push(format!("--important-plugin={}", plugin.display()));
+        }
+        for plugin in conf.disable_plugins.iter() {
+            conf_args.push(format!("--disable-plugin={}", plugin));
+        }
+        if let Some(config_file) = conf.config_file.as_ref() {
+            conf_args.push(format!("--conf={}", config_file.display()));
+        }
+        if let Some(percent) = conf.commit_fee_percent {
+            conf_args.push(format!("--commit-fee={}", percent));
+        }
+        if let Some(offset) = conf.commit_feerate_offset {
+            conf_args.push(format!("--commit-feerate-offset={}", offset));
+        }
+        if let Some(ms) = conf.commit_time_ms {
+            conf_args.push(format!("--commit-time={}", ms));
+        }
+        let cltv = [
+            ("cltv-delta", conf.cltv_delta),
+            ("cltv-final", conf.cltv_final),
+            ("max-locktime-blocks", conf.max_locktime_blocks),
+        ];
+        for (name, value) in cltv {
+            if let Some(value) = value {
+                conf_args.push(format!("--{}={}", name, value));
+            }
+        }
+        for tlv_type in conf.accept_htlc_tlv_types.iter() {
+            conf_args.push(format!("--accept-htlc-tlv-type={}", tlv_type));
+        }
+        if let Some(confirms) = conf.funding_confirms {
+            conf_args.push(format!("--funding-confirms={}", confirms));
+        }
+        if let Some(htlcs) = conf.max_concurrent_htlcs {
+            conf_args.push(format!("--max-concurrent-htlcs={}", htlcs));
+        }
+        if let Some(dsn) = conf.bookkeeper_db.as_ref() {
+            conf_args.push(format!("--bookkeeper-db={}", dsn));
+        }
+        if let Some(upgrade) = conf.database_upgrade {
+            conf_args.push(format!("--database-upgrade={}", upgrade));
+        }
+        if let Some(rpc_file) = conf.rpc_file.as_ref() {
+            conf_args.push(format!("--rpc-file={}", rpc_file.display()));
+        }
+        if let Some(mode) = conf.rpc_file_mode {
+            conf_args.push(format!("--rpc-file-mode={:04o}", mode));
+        }
+        match (conf.wallet_dsn.as_ref(), conf.wallet_path.as_ref()) {
+            (Some(dsn), _) => conf_args.push(format!("--wallet={}", dsn)),
+            (None, Some(path)) => conf_args.push(format!("--wallet=sqlite3://{}", path.display())),
+            (None, None) => (),
+        }
+        let rest_addr = match conf.clnrest_host {
+            Some(host) => {
+                let rest_addr = SocketAddrV4::new(host, get_available_port()?);
+                conf_args.push(format!("--clnrest-host={}", host));
+                conf_args.push(format!("--clnrest-port={}", rest_addr.port()));
+                Some(rest_addr)
+            }
+            None => None,
+        };
+        let autoclean = [
+            ("cycle", conf.autoclean.cycle),
+            ("expiredinvoices-age", conf.autoclean.expiredinvoices_age),
+            ("paidinvoices-age", conf.autoclean.paidinvoices_age),
+            ("succeededpays-age", conf.autoclean.succeededpays_age),
+            ("failedpays-age", conf.autoclean.failedpays_age),
+            (
+                "succeededforwards-age",
+                conf.autoclean.succeededforwards_age,
+            ),
+            ("failedforwards-age", conf.autoclean.failedforwards_age),
+        ];
+        for (name, value) in autoclean {
+            if let Some(value) = value {
+                conf_args.push(format!("--autoclean-{}={}", name, value));
+            }
+        }
+
+        let mut command_line = vec![
+            exe.to_string_lossy().to_string(),
+            format!("--network={}", conf.network),
+            rpcconnect,
+            rpcport,
+            rpcuser,
+            rpcpassword,
+            lightning_dir_arg,
+        ];
+        command_line.extend(p2p_args);
+        command_line.extend(conf_args);
+        // after the managed options, which the reserved ones can't override
+        command_line.extend(conf.args.iter().cloned());
+
+        let mut wrapper = vec![];
+        if conf.enable_coredumps {
+            // the shell raises the core size limit, then is replaced by the command
+            wrapper
+                .extend(["sh", "-c", "ulimit -c unlimited; exec \"$@\"", "sh"].map(String::from));
+        }
+        if let Some(exec_wrapper) = conf.exec_wrapper.as_ref() {
+            wrapper.extend(exec_wrapper.iter().cloned());
+        }
+        if conf.valgrind {
+            wrapper.extend(
+                [
+                    "valgrind",
+                    "-q",
+                    "--trace-children=yes",
+                    "--trace-children-skip=*python*,*bitcoin-cli*",
+                    "--leak-check=full",
+                    "--show-leak-kinds=definite",
+                ]
+                .map(String::from),
+            );
+            let log_file = temp_path.join("valgrind-%p.log");
+            wrapper.push(format!("--log-file={}", log_file.display()));
+        }
+        let exec_wrapper = (!wrapper.is_empty()).then_some(wrapper);
+        let mut command = match exec_wrapper.as_deref() {
+            Some([program, wrapper_args @ ..]) => {
+                let mut command = Command::new(program);
+                command.args(wrapper_args).arg(exe);
+                command
+            }
+            _ => Command::new(exe),
+        };
+        command.args(&command_line[1..]);
+        let path_env = if conf.extra_path.is_empty() {
+            None
+        } else {
+            let current = env::var_os("PATH").unwrap_or_default();
+            let paths = conf
+                .extra_path
+                .iter()
+                .cloned()
+                .chain(env::split_paths(&current));
+            let path = env::join_paths(paths).map_err(|_| Error::InvalidOption("extra_path"))?;
+            command.env("PATH", &path);
+            Some(path)
+        };
+        #[cfg(unix)]
+        if let Some((uid, gid)) = conf.run_as {
+            command.uid(uid).gid(gid);
+        }
+        if let Some(wrapper) = exec_wrapper.as_ref() {
+            command_line.splice(0..0, wrapper.iter().cloned());
+        }
+        debug!("command_line: {:?}", command_line);
+
+        let process = command.stdout(stdout).spawn()?;
+
+        Ok(Launch {
+            process: Spawned(Some(process)),
+            sock_path,
+            work_dir,
+            listen_on,
+            rest_addr,
+            command_line,
+            path_env,
+            proxy,
+            network: conf.network,
+            launched: Instant::now(),
+        })
+    }
+
+    /// Returns [Error::StartupFailed] or [Error::Crashed] if lightningd already exited
+    fn sock_exists(&mut self) -> Result<Option<()>, Error> {
+        let network_dir = self.network_dir();
+        still_running(self.process.child(), &network_dir)?;
+        Ok(self.sock_path.exists().then_some(()))
+    }
+
+    /// Returns [Error::StartupFailed] or [Error::Crashed] if lightningd already exited
+    fn synced_id(&mut self, client: &LightningRPC) -> Result<Option<String>, Error> {
+        let network_dir = self.network_dir();
+        still_running(self.process.child(), &network_dir)?;
+        Ok(synced_id(client))
+    }
+
+    fn network_dir(&self) -> PathBuf {
+        network_dir(self.work_dir.path(), self.network)
+    }
+
+    /// Connects to the peer given in the [Conf] if any and returns the ready [LightningD]
+    fn finish(self, client: LightningRPC, id: String, conf: &Conf) -> Result<LightningD, Error> {
+        #[cfg(unix)]
+        if let Some(mode) = conf.rpc_file_mode {
+            let actual = fs::metadata(&self.sock_path)?.permissions().mode() & 0o777;
+            if actual != mode {
+                return Err(Error::RpcFileModeMismatch(actual));
+            }
+        }
+
+        if !conf.plugin_with_opts.is_empty() {
+            let list: responses::PluginList =
+                client.call("plugin", json!({ "subcommand": "list" }))?;
+            for (plugin, _) in conf.plugin_with_opts.iter() {
+                // lightningd lists the absolute path, the given one may be relative
+                let active = list
+                    .plugins
+                    .iter()
+                    .any(|p| p.active && Path::new(&p.name).file_name() == plugin.file_name());
+                if !active {
+                    return Err(Error::PluginNotLoaded(plugin.clone()));
+                }
+            }
+            let list: serde_json::Value = client.call("listconfigs", json!({}))?;
+            // since v23.08 options are nested in `configs`
+            let configs = list.get("configs").unwrap_or(&list);
+            for (name, value) in conf.plugin_with_opts.iter().flat_map(|(_, opts)| opts) {
+                if !config_has_value(&configs[name], value) {
+                    return Err(Error::PluginOptionNotApplied(name.clone()));
+                }
+            }
+        }
+
+        if let Some(IdHost { id, host }) = conf.p2p.connect.as_ref() {
+            let connect_result = client
+                .connect(id, host.map(|h| h.to_string()).as_deref())
+                .map_err(Error::from_connect)?;
+            debug!("connect_result: {:?}", connect_result);
+        }
+
+        let id_host = self.listen_on.map(|host| IdHost {
+            id,
+            host: Some(host),
+        });
+        Ok(LightningD {
+            process: self.process.take(),
+            client,
+            id_host,
+            command_line: self.command_line,
+            sock_path: self.sock_path,
+            view_stdout: conf.view_stdout,
+            start_timeout: conf.start_timeout,
+            rpc_wait_timeout: conf.rpc_wait_timeout,
+            path_env: self.path_env,
+            #[cfg(unix)]
+            run_as: conf.run_as,
+            valgrind: conf.valgrind,
+            mine_address: OnceLock::new(),
+            #[cfg(target_os = "linux")]
+            paused: AtomicBool::new(false),
+            rest_addr: self.rest_addr,
+            funding_confirms: conf.funding_confirms.unwrap_or(DEFAULT_FUNDING_CONFIRMS),
+            network: self.network,
+            _proxy: self.proxy,
+            _work_dir: self.work_dir,
+        })
+    }
+}
+
+/// Sends `signal` to the process `pid`
+#[cfg(target_os = "linux")]
+fn kill(pid: u32, signal: libc::c_int) -> Result<(), Error> {
+    // SAFETY: kill has no memory safety requirements
+    if unsafe { libc::kill(pid as libc::pid_t, signal) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().into())
+    }
+}
+
+/// Returns the pids of the direct children of the process `pid`, read from `/proc`
+#[cfg(target_os = "linux")]
+fn child_pids(pid: u32) -> Vec<u32> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return vec![];
+    };
+    entries
+        .filter_map(|entry| {
+            let child = entry.ok()?.file_name().to_str()?.parse::<u32>().ok()?;
+            let stat = fs::read_to_string(format!("/proc/{}/stat", child)).ok()?;
+            // the parent pid is the second field after the parenthesized executable name
+            let ppid = stat.rsplit(") ").next()?.split_whitespace().nth(1)?;
+            (ppid.parse::<u32>().ok()? == pid).then_some(child)
+        })
+        .collect()
+}
+
+/// Returns the directory where lightningd keeps the data of `network` in the given lightning-dir,
+/// the network name is used verbatim, even when bitcoind runs a customized regtest chain
+fn network_dir(lightning_dir: &Path, network: Network) -> PathBuf {
+    lightning_dir.join(network.to_string())
+}
+
+/// Returns the path of the rpc socket in the given lightning-dir, `rpc_file` is used verbatim when
+/// absolute while it's relative to the network directory otherwise, like lightningd does
+fn rpc_sock_path(lightning_dir: &Path, network: Network, rpc_file: Option<&Path>) -> PathBuf {
+    let rpc_file = rpc_file.unwrap_or(Path::new("lightning-rpc"));
+    if rpc_file.is_absolute() {
+        rpc_file.to_path_buf()
+    } else {
+        network_dir(lightning_dir, network).join(rpc_file)
+    }
+}
+
+/// Returns [P2P::addr] if it's free to bind, otherwise a localhost address with a free port
+fn listen_addr(p2p: &P2P) -> Result<SocketAddrV4, Error> {
+    match p2p.addr {
+        Some(addr) => match TcpListener::bind(addr) {
+            Ok(_) => Ok(addr),
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => Err(Error::AddrInUse(addr)),
+            Err(e) => Err(e.into()),
+        },
+        None => Ok(SocketAddrV4::new(
+            Ipv4Addr::new(127, 0, 0, 1),
+            get_available_port()?,
+        )),
+    }
+}
+
+/// Returns [Error::StartupFailed] if the process exited, or [Error::Crashed] if it left a core
+/// file in `network_dir`, the working directory of lightningd and its subdaemons
+fn still_running(process: &mut Child, network_dir: &Path) -> Result<(), Error> {
+    match process.try_wait()? {
+        Some(status) => match find_core(network_dir) {
+            Some(core) => Err(Error::Crashed { status, core }),
+            None => Err(Error::StartupFailed(status)),
+        },
+        None => Ok(()),
+    }
+}
+
+/// Returns the errors and the bytes definitely lost reported in a valgrind log, numbers are
+/// printed with thousands separators like `1,024`
+fn parse_valgrind_log(log: &str) -> (u64, u64) {
+    let number_after = |line: &str, marker: &str| {
+        line.split_once(marker).and_then(|(_, rest)| {
+            let digits: String = rest
+                .trim_start()
+                .chars()
+                .take_while(|c| c.is_ascii_digit() || *c == ',')
+                .filter(char::is_ascii_digit)
+                .collect();
+            digits.parse::<u64>().ok()
+        })
+    };
+    let mut errors = 0;
+    let mut leaked_bytes = 0;
+    for line in log.lines() {
+        if let Some(n) = number_after(line, "ERROR SUMMARY:") {
+            errors += n;
+        }
+        if let Some(n) = number_after(line, "definitely lost:") {
+            leaked_bytes += n;
+        }
+    }
+    (errors, leaked_bytes)
+}
+
+/// Returns the first file named like `core` or `core.<pid>` in `dir`, the names given by the
+/// default kernel core pattern. Cores piped to a handler like systemd-coredump aren't found
+fn find_core(dir: &Path) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|e| e.path())
+        .find(|p| {
+            let name = p.file_name().and_then(OsStr::to_str).unwrap_or_default();
+            name == "core"
+                || name
+                    .strip_prefix("core.")
+                    .is_some_and(|pid| pid.chars().all(|c| c.is_ascii_digit()))
+        })
+}
+
+/// Returns `Some` if the bitcoind rpc is answering
+fn bitcoind_ready(bitcoind: &BitcoinD) -> Option<()> {
+    bitcoind.client.get_blockchain_info().ok().map(|_| ())
+}
+
+/// Returns the node id if lightningd answers `getinfo` and isn't syncing
+fn synced_id(client: &LightningRPC) -> Option<String> {
+    let getinfo = client.getinfo().ok()?;
+    (getinfo.warning_bitcoind_sync.is_none() && getinfo.warning_lightningd_sync.is_none())
+        .then_some(getinfo.id)
+}
+
+/// Calls `check` every [POLL_INTERVAL] until it returns `Some` value, returns `error` if it
+/// doesn't after `timeout`
+fn wait_until<T>(
+    timeout: Duration,
+    error: Error,
+    mut check: impl FnMut() -> Result<Option<T>, Error>,
+) -> Result<T, Error> {
+    wait(Waiting::new(timeout, error, move |_: &mut String| check()))
+}
+
+/// Polls `waiting` every [POLL_INTERVAL] until it's done
+fn wait<T>(
+    mut waiting: Waiting<impl FnMut(&mut String) -> Result<Option<T>, Error>>,
+) -> Result<T, Error> {
+    loop {
+        match waiting.poll()? {
+            Some(value) => return Ok(value),
+            None => thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+/// A waiting loop, shared by [wait] and its async version which differ only in how they sleep
+/// between the checks
+struct Waiting<F> {
+    start: Instant,
+    timeout: Duration,
+    error: Option<Error>,
+    /// What `check` saw last, appended to an [Error::Timeout]
+    seen: String,
+    check: F,
+}
+
+impl<F> Waiting<F> {
+    fn new<T>(timeout: Duration, error: Error, check: F) -> Self
+    where
+        F: FnMut(&mut String) -> Result<Option<T>, Error>,
+    {
+        Waiting {
+            start: Instant::now(),
+            timeout,
+            error: Some(error),
+            seen: String::new(),
+            check,
+        }
+    }
+
+    /// Times out with the [Error::Timeout] of `operation` followed by what `check` recorded in
+    /// the given string the last time
+    fn reporting<T>(timeout: Duration, operation: &str, check: F) -> Self
+    where
+        F: FnMut(&mut String) -> Result<Option<T>, Error>,
+    {
+        Self::new(timeout, Error::timeout(operation, timeout), check)
+    }
+
+    /// Calls `check` once, returns `None` if the loop must sleep and poll again
+    fn poll<T>(&mut self) -> Result<Option<T>, Error>
+    where
+        F: FnMut(&mut String) -> Result<Option<T>, Error>,
+    {
+        if let Some(value) = (self.check)(&mut self.seen)? {
+            return Ok(Some(value));
+        }
+        if self.start.elapsed() < self.timeout {
+            return Ok(None);
+        }
+        let error = self.error.take().expect("not polled after timing out");
+        Err(if self.seen.is_empty() {
+            error
+        } else {
+            error.with_last_seen(&self.seen)
+        })
+    }
+}
+
+/// Interval between checks of the various waiting loops
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Default of [Conf::start_timeout]
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default of [Conf::rpc_wait_timeout]
+const RPC_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// lightningd default of `--max-locktime-blocks`
+const DEFAULT_MAX_LOCKTIME_BLOCKS: u32 = 2016;
+
+/// lightningd default of `--funding-confirms`
+const DEFAULT_FUNDING_CONFIRMS: u8 = 3;
+
+/// Sent by [LightningD::open_channel] on top of the channel amount, covering the funding fee and
+/// the emergency reserve lightningd keeps for anchor channels
+const OPEN_CHANNEL_FEE_MARGIN_SAT: u64 = 50_000;
+
+/// lightningd default dust limit, channel outputs below it aren't created and go to fees
+const DUST_LIMIT_SAT: u64 = 546;
+
+/// Status of an `ONCHAIN` channel whose funds are all back in the wallet, not considered pending
+/// by [LightningD::assert_no_pending]
+const ONCHAIN_RESOLVED: &str = "All outputs resolved";
+
+/// Options automatically initialized by [LightningD::with_conf]
+const RESERVED_OPTIONS: [&str; 12] = [
+    "network",
+    "mainnet",
+    "testnet",
+    "signet",
+    "regtest",
+    "lightning-dir",
+    "rpc-file",
+    "conf",
+    "bitcoin-rpcconnect",
+    "bitcoin-rpcport",
+    "bitcoin-rpcuser",
+    "bitcoin-rpcpassword",
+];
+
+/// Returns [Error::ReservedOption] if the config file at `path` contains an option in
+/// [RESERVED_OPTIONS]
+fn validate_config_file(path: &Path) -> Result<(), Error> {
+    for line in fs::read_to_string(path)?.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        let key = line.split('=').next().unwrap_or_default().trim();
+        if RESERVED_OPTIONS.contains(&key) {
+            return Err(Error::ReservedOption(key.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the canonical form of the plugin `path`, so that lightningd doesn't resolve it from its
+/// own working directory, or [Error::PluginNotFound] if it doesn't exist
+fn canonical_plugin(path: &Path) -> Result<PathBuf, Error> {
+    path.canonicalize()
+        .map_err(|_| Error::PluginNotFound(path.to_path_buf()))
+}
+
+/// Checks the given [Conf] before anything is written in the lightning-dir, which may be a
+/// persistent [Conf::work_dir], or the proxy to bitcoind is started
+fn validate(conf: &Conf) -> Result<(), Error> {
+    validate_args(&conf.args)?;
+    if let Some(config_file) = conf.config_file.as_ref() {
+        validate_config_file(config_file)?;
+    }
+    if !conf.developer {
+        let dev_options = [
+            ("dev_force_privkey", conf.dev_force_privkey.is_some()),
+            ("bitcoind_poll_secs", conf.bitcoind_poll_secs.is_some()),
+            ("dev_fast_gossip", conf.dev_fast_gossip),
+            ("dev_fast_gossip_prune", conf.dev_fast_gossip_prune),
+            ("connect_timeout_override", conf.connect_timeout_override),
+            ("dev_black_hole", conf.dev_black_hole),
+            ("dev_disconnect", conf.dev_disconnect.is_some()),
+        ];
+        if let Some((name, _)) = dev_options.iter().find(|(_, set)| *set) {
+            return Err(Error::DeveloperRequired(name));
+        }
+    }
+    if conf.commit_fee_percent == Some(0) {
+        return Err(Error::InvalidOption("commit_fee_percent"));
+    }
+    if conf
+        .commit_time_ms
+        .is_some_and(|ms| u32::try_from(ms).is_err())
+    {
+        return Err(Error::InvalidOption("commit_time_ms"));
+    }
+    let max_locktime = conf
+        .max_locktime_blocks
+        .unwrap_or(DEFAULT_MAX_LOCKTIME_BLOCKS);
+    if max_locktime == 0 {
+        return Err(Error::InvalidOption("max_locktime_blocks"));
+    }
+    let cltv = [
+        ("cltv_delta", conf.cltv_delta),
+        ("cltv_final", conf.cltv_final),
+        ("max_locktime_blocks", conf.max_locktime_blocks),
+    ];
+    for (field, value) in cltv {
+        if value.is_some_and(|value| !(1..=max_locktime).contains(&value)) {
+            return Err(Error::InvalidOption(field));
+        }
+    }
+    if conf.funding_confirms == Some(0) {
+        return Err(Error::InvalidOption("funding_confirms"));
+    }
+    if conf
+        .max_concurrent_htlcs
+        .is_some_and(|htlcs| !(1..=483).contains(&htlcs))
+    {
+        return Err(Error::InvalidOption("max_concurrent_htlcs"));
+    }
+    if conf.wallet_dsn.is_some() && conf.wallet_path.is_some() {
+        return Err(Error::InvalidOption("wallet_path"));
+    }
+    if conf.exec_wrapper.as_ref().is_some_and(Vec::is_empty) {
+        return Err(Error::InvalidOption("exec_wrapper"));
+    }
+    if let Some(config) = conf.bitcoind_proxy.as_ref() {
+        if conf.bitcoin_rpcconnect.is_some()
+            || conf.bitcoin_rpcport.is_some()
+            || config.drop_every == Some(0)
+        {
+            return Err(Error::InvalidOption("bitcoind_proxy"));
+        }
+    }
+    if env::join_paths(conf.extra_path.iter()).is_err() {
+        return Err(Error::InvalidOption("extra_path"));
+    }
+    if conf.bitcoin_rpcuser.is_some() != conf.bitcoin_rpcpassword.is_some() {
+        return Err(Error::MissingAuth);
+    }
+    Ok(())
+}
+
+/// Returns [Error::ReservedOption] if one of the command line `args` sets an option in
+/// [RESERVED_OPTIONS]
+fn validate_args(args: &[String]) -> Result<(), Error> {
+    for arg in args {
+        let key = arg
+            .trim_start_matches('-')
+            .split('=')
+            .next()
+            .unwrap_or_default();
+        if RESERVED_OPTIONS.contains(&key) {
+            return Err(Error::ReservedOption(key.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the BIP39 seed of the given `mnemonic` and `passphrase`, ie. PBKDF2-HMAC-SHA512 with
+/// 2048 iterations. Words must be ASCII since no unicode normalization is done
+fn bip39_seed(mnemonic: &str, passphrase: &str) -> [u8; 64] {
+    let password = mnemonic.split_whitespace().collect::<Vec<_>>().join(" ");
+    let salt = format!("mnemonic{}", passphrase);
+
+    let mut engine = HmacEngine::<sha512::Hash>::new(password.as_bytes());
+    engine.input(salt.as_bytes());
+    engine.input(&1u32.to_be_bytes());
+    let mut u = Hmac::from_engine(engine).to_byte_array();
+    let mut seed = u;
+    for _ in 1..2048 {
+        let mut engine = HmacEngine::<sha512::Hash>::new(password.as_bytes());
+        engine.input(&u);
+        u = Hmac::from_engine(engine).to_byte_array();
+        seed.iter_mut().zip(u.iter()).for_each(|(s, u)| *s ^= u);
+    }
+    seed
+}
+
+/// Returns `true` if the `listconfigs` entry `config` holds `value`, either in a `value_*` or
+/// `values_*` field like since v23.08 or as the bare value of older versions
+fn config_has_value(config: &serde_json::Value, value: &str) -> bool {
+    let matches = |v: &serde_json::Value| match v {
+        serde_json::Value::String(s) => s == value,
+        serde_json::Value::Array(values) => values.iter().any(|v| v.as_str() == Some(value)),
+        serde_json::Value::Number(n) => n.to_string() == value,
+        serde_json::Value::Bool(b) => b.to_string() == value,
+        _ => false,
+    };
+    match config.as_object() {
+        Some(fields) => fields
+            .iter()
+            .any(|(k, v)| k.starts_with("value") && matches(v)),
+        None => matches(config),
+    }
+}
+
+/// Returns `true` if `bit` is set in the hex encoded big-endian `features` bitfield
+fn has_feature_bit(features: &str, bit: usize) -> bool {
+    let start = match features.len().checked_sub((bit / 8 + 1) * 2) {
+        Some(start) => start,
+        None => return false,
+    };
+    features
+        .get(start..start + 2)
+        .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        .map(|byte| byte & (1 << (bit % 8)) != 0)
+        .unwrap_or(false)
+}
+
+/// Used to create unique invoice labels
+static LABEL_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Returns a non-used local port if available.
+///
+/// Note there is a race condition during the time the method check availability and the caller
+pub fn get_available_port() -> Result<u16, Error> {
+    // using 0 as port let the system assign a port available
+    let t = TcpListener::bind(("127.0.0.1", 0))?; // 0 means the OS choose a free port
+    Ok(t.local_addr().map(|s| s.port())?)
+}
+
+/// Returns the path of the lightningd executable downloaded at build time by the enabled
+/// `download_*` feature
+#[cfg(feature = "download")]
+pub fn downloaded_exe_path() -> String {
+    Path::new(env!("OUT_DIR"))
+        .join("lightningd")
+        .join(versions::VERSION)
+        .join("usr")
+        .join("bin")
+        .join("lightningd")
+        .display()
+        .to_string()
+}
+
+/// Returns the lightningd executable pointed by the `LIGHTNINGD_EXE` env var if set, otherwise
+/// the one downloaded by the enabled `download_*` feature, unless the download was skipped with
+/// `LIGHTNINGD_SKIP_DOWNLOAD`
+pub fn exe_path() -> Result<String, Error> {
+    if let Ok(path) = env::var("LIGHTNINGD_EXE") {
+        return Ok(path);
+    }
+    #[cfg(feature = "download")]
+    {
+        let path = downloaded_exe_path();
+        if Path::new(&path).exists() {
+            return Ok(path);
+        }
+    }
+    Err(Error::NoLightningdExecutableFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener};
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use bitcoind::bitcoincore_rpc::RpcApi;
+    use bitcoind::exe_path;
+    use bitcoind::BitcoinD;
+    use log::debug;
+    use log::log_enabled;
+    use log::Level;
+
+    use crate::AddrType;
+    use crate::Autoclean;
+    use crate::ChannelId;
+    use crate::Conf;
+    use crate::Error;
+    use crate::FeeStyle;
+    use crate::IdHost;
+    use crate::LightningD;
+    use crate::LightningFactory;
+    use crate::ListenAnnounce;
+    use crate::MSat;
+    use crate::Network;
+    use crate::PaymentStatus;
+    use crate::ProxyConfig;
+    use crate::P2P;
+    use crate::{
+        bip39_seed, has_feature_bit, parse_valgrind_log, validate, validate_args,
+        validate_config_file, Waiting,
+    };
+
+    #[test]
+    fn valgrind_log() {
+        let log = "==1234== HEAP SUMMARY:
+==1234==     in use at exit: 1,072 bytes in 3 blocks
+==1234== LEAK SUMMARY:
+==1234==    definitely lost: 1,024 bytes in 2 blocks
+==1234==    indirectly lost: 0 bytes in 0 blocks
+==1234== ERROR SUMMARY: 3 errors from 3 contexts (suppressed: 0 from 0)";
+        assert_eq!(parse_valgrind_log(log), (3, 1024));
+        assert_eq!(parse_valgrind_log(""), (0, 0));
+    }
+
+    #[test]
+    fn conf_default() {
+        let conf = Conf::default();
+        assert!(conf.log_timestamps);
+        assert!(conf.args.is_empty());
+        assert!(!conf.developer);
+        assert_eq!(conf.network, Network::Regtest);
+        assert_eq!(conf.start_timeout, Duration::from_secs(30));
+        assert_eq!(conf.rpc_wait_timeout, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn start_timeout() {
+        let bitcoind = init();
+        let conf = Conf {
+            start_timeout: Duration::ZERO,
+            ..Default::default()
+        };
+        let exe = crate::exe_path().unwrap();
+        let result = LightningD::with_conf(exe, &bitcoind, &conf);
+        assert!(
+            matches!(result, Err(Error::SockPathNotExist(elapsed)) if elapsed > Duration::ZERO)
+        );
+    }
+
+    #[test]
+    #[allow(clippy::field_reassign_with_default)]
+    fn one_lightningd() {
+        let bitcoind = init();
+        let mut conf = Conf::default();
+        conf.view_stdout = log_enabled!(Level::Debug);
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::with_conf(exe, &bitcoind, &conf).unwrap();
+        let getinfo = lightningd.client.getinfo().unwrap();
+        debug!("{:?}", getinfo);
+        assert_eq!(getinfo.blockheight, 100);
+    }
+
+    #[test]
+    fn with_bitcoind() {
+        let _ = env_logger::try_init();
+        let exe = crate::exe_path().unwrap();
+        let daemons = LightningD::with_bitcoind(exe, exe_path().unwrap()).unwrap();
+        let lightningd = daemons.lightningd();
+        assert_eq!(lightningd.client.getinfo().unwrap().blockheight, 100);
+        lightningd.generate(daemons.bitcoind(), 1).unwrap();
+    }
+
+    #[test]
+    #[allow(clippy::field_reassign_with_default)]
+    fn two_lightningd() {
+        let bitcoind = init();
+
+        let exe = crate::exe_path().unwrap();
+
+        let mut conf = Conf::default();
+        conf.view_stdout = log_enabled!(Level::Debug);
+        conf.p2p = P2P {
+            listen_announce: ListenAnnounce::Listen,
+            ..Default::default()
+        };
+
+        let lightningd_1 = LightningD::with_conf(&exe, &bitcoind, &conf).unwrap();
+        assert!(lightningd_1.id_host().is_some());
+
+        conf.p2p = P2P {
+            connect: lightningd_1.id_host().cloned(),
+            listen_announce: ListenAnnounce::Listen,
+            ..Default::default()
+        };
+
+        let lightningd_2 = LightningD::with_conf(&exe, &bitcoind, &conf).unwrap();
+        let id_1 = &lightningd_1.id_host().unwrap().id;
+        lightningd_2
+            .wait_for_peer(id_1, Duration::from_secs(10))
+            .unwrap();
+        let list_peers = lightningd_2.client.listpeers(None, None).unwrap();
+        assert_eq!(list_peers.peers.len(), 1);
+    }
+
+    #[test]
+    fn ping() {
+        let (_factory, lightningd_1, lightningd_2) = pair();
+        let id_host_1 = lightningd_1.id_host().unwrap();
+        lightningd_2.connect_to(id_host_1).unwrap();
+        assert!(lightningd_2.ping(&id_host_1.id).unwrap() > 0);
+    }
+
+    #[test]
+    fn num_peers() {
+        let (_factory, lightningd_1, lightningd_2) = pair();
+        assert_eq!(lightningd_2.num_peers().unwrap(), 0);
+        lightningd_2
+            .connect_to(lightningd_1.id_host().unwrap())
+            .unwrap();
+        assert_eq!(lightningd_2.num_peers().unwrap(), 1);
+        assert_eq!(lightningd_2.num_active_channels().unwrap(), 0);
+    }
+
+    #[test]
+    fn reconnect() {
+        let (_factory, lightningd_1, lightningd_2) = pair();
+        lightningd_2
+            .connect_to(lightningd_1.id_host().unwrap())
+            .unwrap();
+        lightningd_2.reconnect(&lightningd_1).unwrap();
+        assert_eq!(lightningd_2.num_peers().unwrap(), 1);
+    }
+
+    #[test]
+    fn p2p_addr_in_use() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let SocketAddr::V4(addr) = taken.local_addr().unwrap() else {
+            unreachable!()
+        };
+        let conf = Conf {
+            p2p: P2P {
+                listen_announce: ListenAnnounce::Listen,
+                addr: Some(addr),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = LightningD::with_conf(exe, &bitcoind, &conf);
+        assert!(matches!(result, Err(Error::AddrInUse(a)) if a == addr));
+    }
+
+    #[test]
+    fn lightning_factory() {
+        let (factory, lightningd_1, lightningd_2) = pair();
+        let id_host_1 = lightningd_1.id_host().unwrap();
+        let id_host_2 = lightningd_2.id_host().unwrap();
+        assert_ne!(id_host_1.id, id_host_2.id);
+        assert_ne!(id_host_1.host, id_host_2.host);
+        assert_ne!(lightningd_1.work_dir(), lightningd_2.work_dir());
+
+        let closed = IdHost {
+            id: id_host_2.id.clone(),
+            host: Some(SocketAddrV4::new(
+                Ipv4Addr::LOCALHOST,
+                crate::get_available_port().unwrap(),
+            )),
+        };
+        let result = lightningd_1.connect_to(&closed);
+        assert!(matches!(result, Err(Error::PeerUnreachable(_))));
+        lightningd_1.connect_to(id_host_2).unwrap();
+
+        let bitcoind = factory.bitcoind();
+        lightningd_1.generate(bitcoind, 1).unwrap();
+        LightningD::fund_all(&[&lightningd_1, &lightningd_2], bitcoind, 100_000).unwrap();
+        let result = LightningD::fund_all(&[], bitcoind, 100_000);
+        assert!(matches!(result, Err(Error::InvalidOption("nodes"))));
+        for lightningd in [&lightningd_1, &lightningd_2] {
+            assert_eq!(lightningd.client.listfunds().unwrap().outputs.len(), 1);
+        }
+        assert_eq!(bitcoind.client.get_block_count().unwrap(), 102);
+    }
+
+    #[test]
+    fn open_channel() {
+        let (factory, lightningd_1, lightningd_2) = pair();
+        let bitcoind = factory.bitcoind();
+        let not_listening = LightningD::new(crate::exe_path().unwrap(), bitcoind).unwrap();
+
+        let result = lightningd_1.open_channel(&not_listening, bitcoind, 100_000, None);
+        assert!(matches!(result, Err(Error::PeerNotListening)));
+
+        lightningd_1.generate(bitcoind, 1).unwrap();
+        let channel = lightningd_1
+            .open_channel(&lightningd_2, bitcoind, 100_000, None)
+            .unwrap();
+        for lightningd in [&lightningd_1, &lightningd_2] {
+            lightningd
+                .wait_all_channels_active(Duration::from_secs(10))
+                .unwrap();
+            assert_eq!(lightningd.peer_channels().unwrap().len(), 1);
+        }
+        assert!(lightningd_2.channel_status(&channel).is_ok());
+
+        let result = lightningd_1.open_channel(&lightningd_2, bitcoind, u64::MAX, None);
+        assert!(matches!(result, Err(Error::InvalidOption("amount_sat"))));
+        let private = lightningd_1
+            .open_private_channel(&lightningd_2, bitcoind, 100_000, Some(10_000_000))
+            .unwrap();
+        let peer_channel = lightningd_2.channel(&private).unwrap();
+        assert_eq!(peer_channel.private, Some(true));
+        assert_eq!(peer_channel.to_us_msat, Some(MSat(10_000_000)));
+    }
+
+    #[test]
+    fn fund_channel_push() {
+        let (factory, lightningd_1, lightningd_2) = pair();
+        let bitcoind = factory.bitcoind();
+        let id = &lightningd_2.id_host().unwrap().id;
+        lightningd_1
+            .connect_to(lightningd_2.id_host().unwrap())
+            .unwrap();
+        lightningd_1.generate(bitcoind, 1).unwrap();
+        lightningd_1
+            .fund_wallet(bitcoind, 1_000_000, AddrType::Bech32)
+            .unwrap();
+        lightningd_1
+            .fund_channel(id, 100_000, Some(10_000_000))
+            .unwrap();
+        lightningd_1.confirm_channel(bitcoind).unwrap();
+        lightningd_2
+            .wait_for_block(106, Duration::from_secs(30))
+            .unwrap();
+        lightningd_1
+            .wait_all_channels_active(Duration::from_secs(30))
+            .unwrap();
+        let channels = lightningd_2.peer_channels().unwrap();
+        assert_eq!(channels[0].to_us_msat, Some(MSat(10_000_000)));
+    }
+
+    #[test]
+    fn wait_channel_state() {
+        let (factory, lightningd_1, lightningd_2) = pair();
+        let bitcoind = factory.bitcoind();
+        let id = &lightningd_2.id_host().unwrap().id;
+        lightningd_1
+            .connect_to(lightningd_2.id_host().unwrap())
+            .unwrap();
+        lightningd_1.generate(bitcoind, 1).unwrap();
+        lightningd_1
+            .fund_wallet(bitcoind, 1_000_000, AddrType::Bech32)
+            .unwrap();
+        let channel = lightningd_1.fund_channel(id, 100_000, None).unwrap();
+        let result =
+            lightningd_1.wait_channel_state(&channel, "CHANNELD_NORMAL", Duration::from_secs(1));
+        assert!(matches!(result, Err(Error::Timeout { operation, .. })
+            if operation.contains("state CHANNELD_AWAITING_LOCKIN")));
+        assert!(!lightningd_1.channel_status(&channel).unwrap().is_empty());
+        lightningd_1.confirm_channel(bitcoind).unwrap();
+        lightningd_1
+            .wait_channel_state(&channel, "CHANNELD_NORMAL", Duration::from_secs(30))
+            .unwrap();
+    }
+
+    #[test]
+    fn invalidate_and_resync() {
+        let (factory, lightningd_1, lightningd_2) = pair();
+        let bitcoind = factory.bitcoind();
+        let id = &lightningd_2.id_host().unwrap().id;
+        lightningd_1
+            .connect_to(lightningd_2.id_host().unwrap())
+            .unwrap();
+        lightningd_1.generate(bitcoind, 1).unwrap();
+        lightningd_1
+            .fund_wallet(bitcoind, 1_000_000, AddrType::Bech32)
+            .unwrap();
+        let channel = lightningd_1.fund_channel(id, 100_000, None).unwrap();
+        let funding_height = bitcoind.client.get_block_count().unwrap() + 1;
+        lightningd_1.confirm_channel(bitcoind).unwrap();
+        lightningd_1
+            .wait_channel_state(&channel, "CHANNELD_NORMAL", Duration::from_secs(30))
+            .unwrap();
+
+        // the block confirming the funding is reorged out, the funding goes back to the mempool
+        // and is confirmed again by the competing chain
+        lightningd_1
+            .invalidate_and_resync(bitcoind, funding_height - 1)
+            .unwrap();
+        let height = bitcoind.client.get_block_count().unwrap();
+        assert_eq!(height, funding_height + 3);
+        assert_eq!(lightningd_1.client.getinfo().unwrap().blockheight, height);
+        let (txid, vout) = lightningd_1.channel_funding(&channel).unwrap();
+        let funding = bitcoind.client.get_tx_out(&txid, vout, None).unwrap();
+        assert_eq!(funding.unwrap().confirmations, 4);
+        lightningd_1
+            .wait_channel_state(&channel, "CHANNELD_NORMAL", Duration::from_secs(30))
+            .unwrap();
+    }
+
+    #[test]
+    fn spendable_receivable() {
+        let (_factory, _lightningd_1, lightningd_2, channel) = channel_pair();
+        // the channel reserve isn't spendable
+        let spendable = lightningd_2.spendable_msat(&channel).unwrap();
+        assert!(spendable > 0 && spendable < 10_000_000);
+        assert!(lightningd_2.receivable_msat(&channel).unwrap() > 0);
+    }
+
+    #[test]
+    fn channel_funding() {
+        let (factory, _lightningd_1, lightningd_2, channel) = channel_pair();
+        let (txid, vout) = lightningd_2.channel_funding(&channel).unwrap();
+        let funding = factory
+            .bitcoind()
+            .client
+            .get_tx_out(&txid, vout, None)
+            .unwrap();
+        assert_eq!(funding.unwrap().value.to_sat(), 100_000);
+    }
+
+    #[test]
+    fn assert_channel_consistent() {
+        let (_factory, lightningd_1, lightningd_2, channel) = channel_pair();
+        lightningd_1
+            .assert_channel_consistent(&lightningd_2, &channel)
+            .unwrap();
+    }
+
+    #[test]
+    fn wait_for_channel_announced() {
+        let (factory, lightningd_1, lightningd_2, channel) = channel_pair();
+        // announced after 6 confirmations, 3 of them mined by confirm_channel
+        lightningd_1.generate(factory.bitcoind(), 3).unwrap();
+        let scid = lightningd_2
+            .wait_for_channel_announced(&channel, Duration::from_secs(60))
+            .unwrap();
+        let channels = lightningd_2.peer_channels().unwrap();
+        assert_eq!(channels[0].short_channel_id, Some(scid));
+    }
+
+    #[test]
+    fn get_route_excluding() {
+        let (factory, lightningd_1, lightningd_2, channel) = channel_pair();
+        lightningd_1.generate(factory.bitcoind(), 3).unwrap();
+        let scid = lightningd_1
+            .wait_for_channel_announced(&channel, Duration::from_secs(60))
+            .unwrap();
+        let id = &lightningd_2.id_host().unwrap().id;
+        assert_eq!(lightningd_1.get_route(id, 1_000).unwrap().len(), 1);
+        let exclude = [format!("{}/0", scid), format!("{}/1", scid)];
+        let exclude: Vec<&str> = exclude.iter().map(String::as_str).collect();
+        let result = lightningd_1.get_route_excluding(id, 1_000, &exclude);
+        assert!(matches!(result, Err(Error::NoRoute)));
+    }
+
+    #[test]
+    fn gossip_store() {
+        let (factory, lightningd_1, _lightningd_2, _channel) = channel_pair();
+        lightningd_1.generate(factory.bitcoind(), 3).unwrap();
+        // both directions of the announced channel
+        lightningd_1
+            .wait_for_gossip_channels(2, Duration::from_secs(60))
+            .unwrap();
+        let conf = Conf {
+            gossip_store: Some(lightningd_1.gossip_store_path()),
+            ..Default::default()
+        };
+        let lightningd_3 = factory.spawn_with_conf(&conf).unwrap();
+        lightningd_3
+            .wait_for_gossip_channels(2, Duration::from_secs(10))
+            .unwrap();
+    }
+
+    #[test]
+    fn verify_channels_survive_restart() {
+        let (_factory, mut lightningd_1, lightningd_2, _channel) = channel_pair();
+        lightningd_1
+            .verify_channels_survive_restart(&lightningd_2)
+            .unwrap();
+    }
+
+    #[test]
+    fn cooperative_close_and_verify() {
+        let (factory, lightningd_1, lightningd_2, channel) = channel_pair();
+        lightningd_1
+            .cooperative_close_and_verify(&lightningd_2, &channel, factory.bitcoind())
+            .unwrap();
+    }
+
+    #[test]
+    fn connect_timeout_override() {
+        let exe = crate::exe_path().unwrap();
+        let factory = LightningFactory::new(exe, init());
+        let conf = Conf {
+            connect_timeout_override: true,
+            ..Default::default()
+        };
+        let result = factory.spawn_with_conf(&conf);
+        assert!(matches!(
+            result,
+            Err(Error::DeveloperRequired("connect_timeout_override"))
+        ));
+
+        let conf = Conf {
+            developer: true,
+            connect_timeout_override: true,
+            ..Default::default()
+        };
+        let lightningd_1 = match factory.spawn_with_conf(&conf) {
+            Err(Error::StartupFailed(_)) => {
+                log::warn!("skipping, lightningd older than v23.11 doesn't have --developer");
+                return;
+            }
+            result => result.unwrap(),
+        };
+        if !lightningd_1.capabilities().unwrap().developer {
+            log::warn!("skipping, lightningd isn't in developer mode");
+            return;
+        }
+        let mut lightningd_2 = factory.spawn_with_conf(&conf).unwrap();
+        lightningd_1
+            .open_channel(&lightningd_2, factory.bitcoind(), 100_000, None)
+            .unwrap();
+        let id = lightningd_2.client.getinfo().unwrap().id;
+
+        // while the peer is down the reconnect backoff of lightningd_1 grows, without the flag
+        // it would be 8 seconds already
+        lightningd_2.shutdown().unwrap();
+        thread::sleep(Duration::from_secs(10));
+        lightningd_2.restart().unwrap();
+        let start = Instant::now();
+        lightningd_1
+            .wait_for_peer(&id, Duration::from_secs(10))
+            .unwrap();
+        let elapsed = start.elapsed();
+        log::info!("peer reconnected {:?} after being back", elapsed);
+        // the fast reconnect bound, plus the polling of wait_for_peer
+        assert!(elapsed < Duration::from_secs(3) + crate::POLL_INTERVAL * 2);
+    }
+
+    #[test]
+    fn private_channel_route_hint() {
+        let (factory, lightningd_1, lightningd_2) = pair();
+        let bitcoind = factory.bitcoind();
+        let id = &lightningd_2.id_host().unwrap().id;
+        lightningd_1
+            .connect_to(lightningd_2.id_host().unwrap())
+            .unwrap();
+
+        lightningd_1.generate(bitcoind, 1).unwrap();
+        lightningd_1
+            .fund_wallet(bitcoind, 1_000_000, AddrType::Bech32)
+            .unwrap();
+        let channel = lightningd_1
+            .fund_private_channel(id, 100_000, None)
+            .unwrap();
+        lightningd_1.confirm_channel(bitcoind).unwrap();
+        lightningd_1
+            .wait_all_channels_active(Duration::from_secs(30))
+            .unwrap();
+        assert_eq!(lightningd_1.channel(&channel).unwrap().private, Some(true));
+
+        let hint = crate::wait_until(
+            Duration::from_secs(30),
+            Error::timeout("route_hint", Duration::from_secs(30)),
+            || match lightningd_2.route_hint(&channel) {
+                Err(Error::RouteHintUnavailable) => Ok(None),
+                r => r.map(Some),
+            },
+        )
+        .unwrap();
+        assert_eq!(hint.id, lightningd_1.id_host().unwrap().id);
+        assert_eq!(hint.cltv_expiry_delta, 6);
+
+        let invoice = lightningd_2
+            .client
+            .invoice(Some(1_000_000), "private", "private", None, None, None)
+            .unwrap();
+        // the invoice carries the hint of the private channel
+        lightningd_1.pay(&invoice.bolt11).unwrap();
+    }
+
+    #[test]
+    fn send_pays() {
+        let (_factory, lightningd_1, lightningd_2, _channel) = channel_pair();
+        lightningd_1
+            .wait_all_channels_active(Duration::from_secs(30))
+            .unwrap();
+        let pay = lightningd_1.pay_to(&lightningd_2, 1_000_000).unwrap();
+        let parts = lightningd_1.send_pays(Some(&pay.payment_hash)).unwrap();
+        assert!(!parts.is_empty());
+        assert!(parts.iter().any(|p| p.status == "complete"));
+        assert!(lightningd_1
+            .send_pays(Some(&"00".repeat(32)))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn payment_status() {
+        let (_factory, lightningd_1, lightningd_2, _channel) = channel_pair();
+        lightningd_1
+            .wait_all_channels_active(Duration::from_secs(30))
+            .unwrap();
+        let pay = lightningd_1.pay_to(&lightningd_2, 1_000_000).unwrap();
+        assert_eq!(
+            lightningd_1.payment_status(&pay.payment_hash).unwrap(),
+            PaymentStatus::Complete
+        );
+        let result = lightningd_1.payment_status(&"00".repeat(32));
+        assert!(matches!(result, Err(Error::PaymentNotFound)));
+    }
+
+    #[test]
+    fn list_htlcs() {
+        let (_factory, lightningd_1, lightningd_2, channel) = channel_pair();
+        lightningd_1
+            .wait_all_channels_active(Duration::from_secs(30))
+            .unwrap();
+        let pay = lightningd_1.pay_to(&lightningd_2, 1_000_000).unwrap();
+        let htlcs = lightningd_1.list_htlcs(Some(&channel)).unwrap();
+        assert_eq!(htlcs.len(), 1);
+        assert_eq!(htlcs[0].direction, "out");
+        assert_eq!(htlcs[0].payment_hash, pay.payment_hash);
+        assert_eq!(lightningd_2.list_htlcs(None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn disable_dns() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let conf = Conf {
+            disable_dns: true,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let lightningd = LightningD::with_conf(exe, &bitcoind, &conf).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(lightningd
+            .command_line()
+            .contains(&"--disable-dns".to_string()));
+        lightningd.client.getinfo().unwrap();
+    }
+
+    #[test]
+    fn fund_wallet() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::new(exe, &bitcoind).unwrap();
+
+        // coinbase of block 1 is spendable only after 101 blocks
+        lightningd.generate(&bitcoind, 1).unwrap();
+        assert_eq!(lightningd.client.getinfo().unwrap().blockheight, 101);
+        assert_eq!(
+            lightningd
+                .wait_block_height(101, Duration::from_secs(1))
+                .unwrap(),
+            101
+        );
+
+        let txid = lightningd
+            .fund_wallet(&bitcoind, 100_000, AddrType::Bech32)
+            .unwrap();
+        let funds = lightningd.client.listfunds().unwrap();
+        assert!(funds.outputs.iter().any(|o| o.txid == txid.to_string()));
+        let balance = lightningd
+            .wait_for_onchain_funds(100_000, Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(balance, 100_000);
+        lightningd.assert_no_pending().unwrap();
+    }
+
+    #[test]
+    fn assert_no_pending_force_close() {
+        let (factory, lightningd_1, lightningd_2) = pair();
+        let bitcoind = factory.bitcoind();
+        lightningd_1.generate(bitcoind, 1).unwrap();
+        let channel = lightningd_1
+            .open_channel(&lightningd_2, bitcoind, 100_000, None)
+            .unwrap();
+        lightningd_1.assert_no_pending().unwrap();
+
+        // a connected peer would agree on a mutual close within the unilateral timeout
+        let id = lightningd_2.client.getinfo().unwrap().id;
+        let _: serde_json::Value = lightningd_1
+            .client
+            .call("disconnect", serde_json::json!({ "id": id, "force": true }))
+            .unwrap();
+        let _: serde_json::Value = lightningd_1
+            .client
+            .call(
+                "close",
+                serde_json::json!({ "id": channel.0, "unilateraltimeout": 1 }),
+            )
+            .unwrap();
+        lightningd_1.generate(bitcoind, 1).unwrap();
+        // the to-self output is delayed, so the funds are still waiting on onchaind
+        lightningd_1
+            .wait_channel_state(&channel, "ONCHAIN", Duration::from_secs(30))
+            .unwrap();
+        let result = lightningd_1.assert_no_pending();
+        assert!(matches!(result, Err(Error::Pending(pending))
+            if pending.iter().any(|p| p.contains("in state ONCHAIN"))));
+    }
+
+    #[test]
+    fn bump_close_fee() {
+        let exe = crate::exe_path().unwrap();
+        let factory = LightningFactory::new(exe, init());
+        let bitcoind = factory.bitcoind();
+        let conf = Conf {
+            developer: true,
+            experimental_anchors: true,
+            ..Default::default()
+        };
+        let lightningd_1 = factory.spawn_with_conf(&conf).unwrap();
+        let lightningd_2 = factory.spawn_with_conf(&conf).unwrap();
+        lightningd_1.generate(bitcoind, 1).unwrap();
+        let channel = lightningd_1
+            .open_channel(&lightningd_2, bitcoind, 100_000, None)
+            .unwrap();
+        let (funding_txid, _) = lightningd_1.channel_funding(&channel).unwrap();
+
+        let child = lightningd_1.bump_close_fee(&channel, bitcoind).unwrap();
+        let entry = bitcoind.client.get_mempool_entry(&child).unwrap();
+        let commitment = bitcoind
+            .client
+            .get_raw_transaction(&entry.depends[0], None)
+            .unwrap();
+        assert!(commitment
+            .input
+            .iter()
+            .any(|input| input.previous_output.txid == funding_txid));
+    }
+
+    #[test]
+    fn bitcoind_proxy() {
+        let bitcoind = init();
+        let conf = Conf {
+            bitcoind_proxy: Some(ProxyConfig {
+                delay: Duration::from_millis(100),
+                drop_every: Some(5),
+            }),
+            ..Default::default()
+        };
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::with_conf(exe, &bitcoind, &conf).unwrap();
+        lightningd.generate(&bitcoind, 1).unwrap();
+        assert_eq!(lightningd.client.getinfo().unwrap().blockheight, 101);
+    }
+
+    #[test]
+    fn proxy_delay() {
+        use std::io::{Read, Write};
+
+        // answers every request on the connection with its own bytes
+        let echo = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let SocketAddr::V4(target) = echo.local_addr().unwrap() else {
+            unreachable!()
+        };
+        thread::spawn(move || {
+            let (mut stream, _) = echo.accept().unwrap();
+            let mut buf = [0u8; 16];
+            while let Ok(n @ 1..) = stream.read(&mut buf) {
+                stream.write_all(&buf[..n]).unwrap();
+            }
+        });
+        let config = ProxyConfig {
+            delay: Duration::from_millis(100),
+            drop_every: None,
+        };
+        let proxy = crate::proxy::Proxy::start(target, config).unwrap();
+
+        let mut client = std::net::TcpStream::connect(proxy.addr()).unwrap();
+        for request in [b"a", b"b"] {
+            let start = Instant::now();
+            client.write_all(request).unwrap();
+            let mut response = [0u8; 1];
+            client.read_exact(&mut response).unwrap();
+            assert_eq!(&response, request);
+            assert!(start.elapsed() >= Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn sign_message() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::new(exe, &bitcoind).unwrap();
+        let id = lightningd.client.getinfo().unwrap().id;
+
+        let signature = lightningd.sign_message("hello").unwrap();
+        assert!(lightningd
+            .check_message("hello", &signature.zbase, Some(&id))
+            .unwrap());
+        assert!(!lightningd
+            .check_message("bye", &signature.zbase, Some(&id))
+            .unwrap());
+    }
+
+    #[test]
+    fn sync_warnings() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::new(exe, &bitcoind).unwrap();
+        assert_eq!(lightningd.sync_warnings().unwrap(), (None, None));
+    }
+
+    #[test]
+    fn gossip_store_path() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::new(exe, &bitcoind).unwrap();
+        assert!(lightningd.gossip_store_path().exists());
+    }
+
+    #[test]
+    fn work_dir() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::new(exe, &bitcoind).unwrap();
+        assert!(lightningd.work_dir().join("regtest").exists());
+    }
+
+    #[test]
+    fn feerates() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::new(exe, &bitcoind).unwrap();
+        let feerates = lightningd.feerates(FeeStyle::PerKw).unwrap();
+        assert!(feerates.min_acceptable.is_some());
+    }
+
+    #[test]
+    fn capabilities() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::new(exe, &bitcoind).unwrap();
+        let capabilities = lightningd.capabilities().unwrap();
+        let getinfo = lightningd.client.getinfo().unwrap();
+        assert_eq!(capabilities.version, getinfo.version);
+        assert!(!capabilities.developer);
+    }
+
+    #[test]
+    fn wait_for_log_rpc() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::new(exe, &bitcoind).unwrap();
+        lightningd
+            .wait_for_log_rpc("Server started", Duration::from_secs(10))
+            .unwrap();
+    }
+
+    #[test]
+    fn work_dir_in_use() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let dir = tempfile::TempDir::new().unwrap();
+        let conf = Conf {
+            work_dir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        let _lightningd = LightningD::with_conf(&exe, &bitcoind, &conf).unwrap();
+        let result = LightningD::with_conf(&exe, &bitcoind, &conf);
+        assert!(matches!(result, Err(Error::DirInUse)));
+    }
+
+    #[test]
+    fn disable_plugins() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let conf = Conf {
+            disable_plugins: vec!["pay".to_string()],
+            ..Default::default()
+        };
+        let lightningd = LightningD::with_conf(exe, &bitcoind, &conf).unwrap();
+
+        let plugins: serde_json::Value = lightningd
+            .client
+            .call("plugin", serde_json::json!({ "subcommand": "list" }))
+            .unwrap();
+        let names: Vec<_> = plugins["plugins"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["name"].as_str().unwrap())
+            .collect();
+        assert!(!names.iter().any(|n| n.ends_with("/pay")));
+        assert!(!names.is_empty());
+    }
+
+    #[test]
+    fn plugins() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let plugin_dir = dir.path().join("plugins");
+        std::fs::create_dir(&plugin_dir).unwrap();
+        // answers getmanifest and init, the only requests lightningd sends to a plugin without
+        // methods, hooks or subscriptions
+        let script = r#"#!/usr/bin/env python3
+import json, sys
+for line in sys.stdin:
+    if line.strip():
+        request = json.loads(line)
+        result = {"options": [], "rpcmethods": [], "dynamic": True} if request["method"] == "getmanifest" else {}
+        print(json.dumps({"jsonrpc": "2.0", "id": request["id"], "result": result}) + "\n", flush=True)
+"#;
+        for path in [dir.path().join("my plugin"), plugin_dir.join("dirplugin")] {
+            std::fs::write(&path, script).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let conf = Conf {
+            plugins: vec![dir.path().join("missing")],
+            ..Default::default()
+        };
+        let result = LightningD::with_conf(&exe, &bitcoind, &conf);
+        assert!(matches!(result, Err(Error::PluginNotFound(p)) if p.ends_with("missing")));
+        let conf = Conf {
+            plugin_with_opts: vec![(dir.path().join("missing"), vec![])],
+            ..Default::default()
+        };
+        let result = LightningD::with_conf(&exe, &bitcoind, &conf);
+        assert!(matches!(result, Err(Error::PluginNotFound(p)) if p.ends_with("missing")));
+
+        let conf = Conf {
+            plugins: vec![dir.path().join("plugins/../my plugin")],
+            plugin_dir: Some(plugin_dir),
+            ..Default::default()
+        };
+        let lightningd = LightningD::with_conf(&exe, &bitcoind, &conf).unwrap();
+        let plugins: serde_json::Value = lightningd
+            .client
+            .call("plugin", serde_json::json!({ "subcommand": "list" }))
+            .unwrap();
+        let active: Vec<_> = plugins["plugins"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|p| p["active"].as_bool().unwrap())
+            .map(|p| p["name"].as_str().unwrap())
+            .collect();
+        assert!(active
+            .iter()
+            .any(|n| n.ends_with("/my plugin") && !n.contains("..")));
+        assert!(active.iter().any(|n| n.ends_with("/dirplugin")));
+    }
+
+    #[test]
+    fn in_memory_wallet() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let conf = Conf {
+            wallet_dsn: Some("sqlite3://:memory:".to_string()),
+            ..Default::default()
+        };
+        let lightningd = LightningD::with_conf(exe, &bitcoind, &conf).unwrap();
+        assert_eq!(lightningd.client.getinfo().unwrap().blockheight, 100);
+    }
+
+    #[test]
+    fn wallet_path() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let dir = tempfile::TempDir::new().unwrap();
+        let wallet_path = dir.path().join("wallet.sqlite3");
+        let conf = Conf {
+            wallet_path: Some(wallet_path.clone()),
+            ..Default::default()
+        };
+        let _lightningd = LightningD::with_conf(exe, &bitcoind, &conf).unwrap();
+        assert!(wallet_path.exists());
+    }
+
+    #[test]
+    fn decode_invoice() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::new(exe, &bitcoind).unwrap();
+        let invoice = lightningd
+            .client
+            .invoice(Some(1_000), "label", "description", None, Some(600), None)
+            .unwrap();
+
+        let decoded = lightningd.decode(&invoice.bolt11).unwrap();
+        assert!(decoded.valid);
+        assert_eq!(decoded.amount_msat, Some(MSat(1_000)));
+        assert_eq!(decoded.description.as_deref(), Some("description"));
+        assert_eq!(decoded.expiry, Some(600));
+    }
+
+    #[test]
+    fn autoclean_expired_invoices() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let conf = Conf {
+            autoclean: Autoclean {
+                cycle: Some(1),
+                expiredinvoices_age: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let lightningd = LightningD::with_conf(exe, &bitcoind, &conf).unwrap();
+        lightningd
+            .client
+            .invoice(Some(1_000), "expiring", "description", None, Some(1), None)
+            .unwrap();
+        assert_eq!(lightningd.invoices().unwrap().len(), 1);
+
+        for _ in 0..30 {
+            if lightningd.invoices().unwrap().is_empty() {
+                return;
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+        panic!("expired invoice not cleaned");
+    }
+
+    #[test]
+    fn del_invoice() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::new(exe, &bitcoind).unwrap();
+        lightningd
+            .client
+            .invoice(Some(1_000), "to_delete", "description", None, None, None)
+            .unwrap();
+        assert_eq!(lightningd.invoices().unwrap().len(), 1);
+        lightningd.del_invoice("to_delete", "unpaid").unwrap();
+        assert!(lightningd.invoices().unwrap().is_empty());
+    }
+
+    #[test]
+    fn config_file_reserved_option() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config");
+
+        std::fs::write(&path, "# comment\nalias=test\nlog-level=debug\n").unwrap();
+        validate_config_file(&path).unwrap();
+
+        std::fs::write(&path, "alias=test\nnetwork=bitcoin\n").unwrap();
+        let result = validate_config_file(&path);
+        assert!(matches!(result, Err(Error::ReservedOption(key)) if key == "network"));
+    }
+
+    #[test]
+    fn args_reserved_option() {
+        validate_args(&["--alias=test".to_string(), "--rgb=AABBCC".to_string()]).unwrap();
+        let result = validate_args(&["--lightning-dir=/tmp".to_string()]);
+        assert!(matches!(result, Err(Error::ReservedOption(key)) if key == "lightning-dir"));
+        for arg in ["--testnet", "--rpc-file=other-rpc"] {
+            let result = validate_args(&[arg.to_string()]);
+            assert!(matches!(result, Err(Error::ReservedOption(_))));
+        }
+    }
+
+    #[test]
+    fn validate_conf() {
+        validate(&Conf::default()).unwrap();
+        let conf = Conf {
+            dev_fast_gossip: true,
+            ..Default::default()
+        };
+        let result = validate(&conf);
+        assert!(matches!(
+            result,
+            Err(Error::DeveloperRequired("dev_fast_gossip"))
+        ));
+        let conf = Conf {
+            developer: true,
+            dev_fast_gossip: true,
+            ..Default::default()
+        };
+        validate(&conf).unwrap();
+        let conf = Conf {
+            funding_confirms: Some(0),
+            ..Default::default()
+        };
+        let result = validate(&conf);
+        assert!(matches!(
+            result,
+            Err(Error::InvalidOption("funding_confirms"))
+        ));
+        let conf = Conf {
+            exec_wrapper: Some(vec![]),
+            ..Default::default()
+        };
+        let result = validate(&conf);
+        assert!(matches!(result, Err(Error::InvalidOption("exec_wrapper"))));
+    }
+
+    #[test]
+    fn args() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let conf = Conf {
+            args: vec!["--alias=testnode".to_string(), "--rgb=AABBCC".to_string()],
+            ..Default::default()
+        };
+        let lightningd = LightningD::with_conf(&exe, &bitcoind, &conf).unwrap();
+        let getinfo = lightningd.client.getinfo().unwrap();
+        assert_eq!(getinfo.alias, "testnode");
+        assert_eq!(getinfo.color, "aabbcc");
+
+        // rejected before the persistent work_dir is touched
+        let work_dir = tempfile::tempdir().unwrap();
+        let conf = Conf {
+            args: vec!["--network=testnet".to_string()],
+            work_dir: Some(work_dir.path().to_path_buf()),
+            gossip_store: Some(lightningd.gossip_store_path()),
+            ..Default::default()
+        };
+        let result = LightningD::with_conf(&exe, &bitcoind, &conf);
+        assert!(matches!(result, Err(Error::ReservedOption(key)) if key == "network"));
+        assert!(!work_dir.path().join("regtest").exists());
+    }
+
+    #[test]
+    fn rpc_sock_path() {
+        let dir = Path::new("/tmp/ln");
+        assert_eq!(
+            super::rpc_sock_path(dir, Network::Regtest, None),
+            Path::new("/tmp/ln/regtest/lightning-rpc")
+        );
+        assert_eq!(
+            super::rpc_sock_path(dir, Network::Regtest, Some(Path::new("rpc"))),
+            Path::new("/tmp/ln/regtest/rpc")
+        );
+        assert_eq!(
+            super::rpc_sock_path(dir, Network::Regtest, Some(Path::new("/run/ln/rpc"))),
+            Path::new("/run/ln/rpc")
+        );
+        assert_eq!(
+            super::rpc_sock_path(dir, Network::Signet, None),
+            Path::new("/tmp/ln/signet/lightning-rpc")
+        );
+        assert_eq!(
+            super::rpc_sock_path(dir, Network::Bitcoin, None),
+            Path::new("/tmp/ln/bitcoin/lightning-rpc")
+        );
+    }
+
+    #[test]
+    fn absolute_rpc_file() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let dir = tempfile::TempDir::new().unwrap();
+        let rpc_file = dir.path().join("rpc");
+        let conf = Conf {
+            rpc_file: Some(rpc_file.clone()),
+            ..Default::default()
+        };
+        let lightningd = LightningD::with_conf(exe, &bitcoind, &conf).unwrap();
+        assert!(rpc_file.exists());
+        assert_eq!(lightningd.client.getinfo().unwrap().blockheight, 100);
+    }
+
+    #[test]
+    fn exec_wrapper() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let conf = Conf {
+            exec_wrapper: Some(vec!["env".to_string()]),
+            ..Default::default()
+        };
+        let lightningd = LightningD::with_conf(&exe, &bitcoind, &conf).unwrap();
+        assert_eq!(lightningd.command_line()[..2], ["env".to_string(), exe]);
+        assert_eq!(lightningd.client.getinfo().unwrap().blockheight, 100);
+    }
+
+    #[test]
+    fn extra_path() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let conf = Conf {
+            extra_path: vec![dir.path().to_path_buf()],
+            ..Default::default()
+        };
+        let mut lightningd = LightningD::with_conf(&exe, &bitcoind, &conf).unwrap();
+        let expected = format!("PATH={}:", dir.path().display());
+        let environ_path = |lightningd: &LightningD| {
+            let environ = std::fs::read(format!("/proc/{}/environ", lightningd.process.id()));
+            String::from_utf8_lossy(&environ.unwrap())
+                .split('\0')
+                .any(|var| var.starts_with(&expected))
+        };
+        assert!(environ_path(&lightningd));
+        lightningd.restart().unwrap();
+        assert!(environ_path(&lightningd));
+        assert_eq!(lightningd.client().getinfo().unwrap().blockheight, 100);
+    }
+
+    #[test]
+    fn enable_coredumps() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let conf = Conf {
+            enable_coredumps: true,
+            exec_wrapper: Some(vec!["env".to_string()]),
+            ..Default::default()
+        };
+        let lightningd = LightningD::with_conf(&exe, &bitcoind, &conf).unwrap();
+        assert_eq!(lightningd.command_line()[0], "sh");
+        assert_eq!(lightningd.command_line()[4..6], ["env".to_string(), exe]);
+        assert_eq!(lightningd.client.getinfo().unwrap().blockheight, 100);
+        assert_eq!(crate::find_core(&lightningd.network_dir()), None);
+    }
+
+    #[test]
+    fn stop() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::new(exe, &bitcoind).unwrap();
+        let pid = lightningd.process.id();
+        let sock_path = lightningd.sock_path.clone();
+        lightningd.stop().unwrap();
+        assert!(!Path::new(&format!("/proc/{}", pid)).exists());
+        assert!(!sock_path.exists());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn pause_resume() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::new(exe, &bitcoind).unwrap();
+        let state = |lightningd: &LightningD| {
+            let stat = std::fs::read_to_string(format!("/proc/{}/stat", lightningd.process.id()));
+            // the state follows the parenthesized executable name
+            stat.unwrap().rsplit(") ").next().unwrap().chars().next()
+        };
+        lightningd.pause().unwrap();
+        assert_eq!(state(&lightningd), Some('T'));
+        lightningd.resume().unwrap();
+        assert_ne!(state(&lightningd), Some('T'));
+        assert_eq!(lightningd.client.getinfo().unwrap().blockheight, 100);
+
+        // resumed before the stop rpc
+        lightningd.pause().unwrap();
+        lightningd.stop().unwrap();
+    }
+
+    #[test]
+    fn from_pay() {
+        let rpc_error = |code: i32, data: serde_json::Value| {
+            // the rpc error type isn't reexported, it's inferred from the variant
+            clightningrpc::Error::Rpc(
+                serde_json::from_value(serde_json::json!({
+                    "code": code,
+                    "message": "failed",
+                    "data": data,
+                }))
+                .unwrap(),
+            )
+        };
+        let data = serde_json::json!({
+            "failcode": 16399,
+            "failcodename": "WIRE_INCORRECT_OR_UNKNOWN_PAYMENT_DETAILS",
+            "erring_node": "02aa",
+        });
+        let error = Error::from_pay(rpc_error(204, data.clone()));
+        assert!(matches!(error, Error::Payment {
+            failcode: Some(16399),
+            failcodename: Some(name),
+            erring_node: Some(node),
+        } if name == "WIRE_INCORRECT_OR_UNKNOWN_PAYMENT_DETAILS" && node == "02aa"));
+        for code in [200, 219] {
+            let error = Error::from_pay(rpc_error(code, serde_json::Value::Null));
+            assert!(matches!(
+                error,
+                Error::Payment {
+                    failcode: None,
+                    failcodename: None,
+                    erring_node: None,
+                }
+            ));
+        }
+        for code in [199, 220, -32602] {
+            let error = Error::from_pay(rpc_error(code, data.clone()));
+            assert!(matches!(error, Error::Rpc(clightningrpc::Error::Rpc(e)) if e.code == code));
+        }
+    }
+
+    #[test]
+    fn config_has_value() {
+        let configs = serde_json::json!({
+            "myplugin-port": { "value_int": 1234, "source": "cmdline", "plugin": "/p" },
+            "myplugin-name": { "value_str": "test", "source": "cmdline", "plugin": "/p" },
+            "myplugin-peer": { "values_str": ["a", "b"], "sources": ["cmdline", "cmdline"] },
+        });
+        assert!(crate::config_has_value(&configs["myplugin-port"], "1234"));
+        assert!(crate::config_has_value(&configs["myplugin-name"], "test"));
+        assert!(crate::config_has_value(&configs["myplugin-peer"], "b"));
+        assert!(!crate::config_has_value(&configs["myplugin-name"], "other"));
+        assert!(!crate::config_has_value(&configs["missing"], "test"));
+        // before v23.08
+        assert!(crate::config_has_value(&serde_json::json!(1234), "1234"));
+    }
+
+    #[test]
+    fn waiting() {
+        let mut polls = 0;
+        let mut waiting = Waiting::reporting(Duration::ZERO, "test", |seen| {
+            polls += 1;
+            *seen = format!("{} polls", polls);
+            Ok(None::<()>)
+        });
+        let result = waiting.poll();
+        assert!(matches!(result, Err(Error::Timeout { operation, .. })
+            if operation == "test, last seen: 1 polls"));
+
+        let result = crate::wait_until(Duration::from_secs(1), Error::NoRoute, || Ok(Some(3)));
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn child_pids() {
+        let mut parent = std::process::Command::new("sh")
+            .args(["-c", "sleep 10 & wait"])
+            .spawn()
+            .unwrap();
+        let pid = parent.id();
+        let start = Instant::now();
+        let children = loop {
+            let children = crate::child_pids(pid);
+            if !children.is_empty() || start.elapsed() > Duration::from_secs(5) {
+                break children;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(children.len(), 1);
+        for child in children {
+            crate::kill(child, libc::SIGKILL).unwrap();
+        }
+        assert!(parent.wait().unwrap().success());
+        assert!(crate::kill(pid, libc::SIGCONT).is_err());
+    }
+
+    #[test]
+    fn startup_failed() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let conf = Conf {
+            // an unknown option makes lightningd exit right away
+            args: vec!["--no-such-option=1".into()],
+            ..Default::default()
+        };
+        let result = LightningD::with_conf(exe, &bitcoind, &conf);
+        assert!(matches!(result, Err(Error::StartupFailed(status)) if !status.success()));
+    }
+
+    #[test]
+    fn custom_regtest() {
+        let _ = env_logger::try_init();
+        let mut bitcoind_conf = bitcoind::Conf::default();
+        // -blockversion is a regtest-only override that changes the header of every mined block
+        bitcoind_conf.args = vec!["-regtest", "-fallbackfee=0.0001", "-blockversion=536870928"];
+        let bitcoind = BitcoinD::with_conf(exe_path().unwrap(), &bitcoind_conf).unwrap();
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::new(exe, &bitcoind).unwrap();
+        let hashes = lightningd.generate(&bitcoind, 1).unwrap();
+        let header = bitcoind.client.get_block_header(&hashes[0]).unwrap();
+        assert_eq!(header.version.to_consensus(), 0x2000_0010);
+        assert_eq!(
+            lightningd.client.getinfo().unwrap().blockheight as u64,
+            bitcoind.client.get_block_count().unwrap()
+        );
+        assert!(lightningd.work_dir().join("regtest").exists());
+        assert_eq!(lightningd.network(), crate::Network::Regtest);
+        assert!(lightningd
+            .gossip_store_path()
+            .starts_with(lightningd.work_dir()));
+    }
+
+    #[test]
+    fn bip39_seed_vector() {
+        // from https://github.com/trezor/python-mnemonic/blob/master/vectors.json
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let seed = bip39_seed(mnemonic, "TREZOR");
+        let hex: String = seed.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04");
+    }
+
+    #[test]
+    fn from_mnemonic() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let id_1 = {
+            let lightningd = LightningD::from_mnemonic(&exe, &bitcoind, mnemonic).unwrap();
+            lightningd.client.getinfo().unwrap().id
+        };
+        let lightningd = LightningD::from_mnemonic(&exe, &bitcoind, mnemonic).unwrap();
+        assert_eq!(lightningd.client.getinfo().unwrap().id, id_1);
+    }
+
+    #[test]
+    fn feature_bits() {
+        assert!(has_feature_bit("01", 0));
+        assert!(!has_feature_bit("01", 1));
+        assert!(has_feature_bit("0200", 9));
+        assert!(!has_feature_bit("0200", 8));
+        assert!(has_feature_bit("80000000", 31));
+        assert!(!has_feature_bit("80", 8));
+        assert!(!has_feature_bit("", 0));
+        assert!(!has_feature_bit("zz", 0));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn one_lightningd_async() {
+        let bitcoind = init();
+        let exe = crate::exe_path().unwrap();
+        let lightningd = LightningD::new_async(exe, &bitcoind).await.unwrap();
+        lightningd
+            .wait_for_block_async(100, Duration::from_secs(10))
+            .await
+            .unwrap();
+    }
+
+    fn init() -> BitcoinD {
+        let _ = env_logger::try_init();
+        let bitcoind_exe = exe_path().unwrap();
+        let bitcoind = BitcoinD::new(bitcoind_exe).unwrap();
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked();
+        bitcoind.client.generate_to_address(100, &address).unwrap();
+        bitcoind
+    }
+
+    /// Spawns two nodes of a new [LightningFactory], not connected to each other
+    fn pair() -> (LightningFactory, LightningD, LightningD) {
+        let exe = crate::exe_path().unwrap();
+        let factory = LightningFactory::new(exe, init());
+        let lightningd_1 = factory.spawn().unwrap();
+        let lightningd_2 = factory.spawn().unwrap();
+        (factory, lightningd_1, lightningd_2)
+    }
+
+    /// Like [pair], with a confirmed channel of 100_000 sat opened by the first node pushing
+    /// 10_000_000 msat to the second
+    fn channel_pair() -> (LightningFactory, LightningD, LightningD, ChannelId) {
+        let (factory, lightningd_1, lightningd_2) = pair();
+        let bitcoind = factory.bitcoind();
+        lightningd_1.generate(bitcoind, 1).unwrap();
+        let channel = lightningd_1
+            .open_channel(&lightningd_2, bitcoind, 100_000, Some(10_000_000))
+            .unwrap();
+        (factory, lightningd_1, lightningd_2, channel)
     }
 }
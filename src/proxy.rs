@@ -0,0 +1,101 @@
+//! TCP proxy between lightningd and the bitcoind rpc, injecting delays and failures
+
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use log::debug;
+
+use crate::conf::ProxyConfig;
+
+/// A running proxy, it stops accepting connections when dropped
+pub(crate) struct Proxy {
+    addr: SocketAddrV4,
+    stop: Arc<AtomicBool>,
+}
+
+impl Proxy {
+    /// Starts listening on a free local port, forwarding every connection to `target`
+    pub(crate) fn start(target: SocketAddrV4, config: ProxyConfig) -> Result<Self, io::Error> {
+        let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))?;
+        let SocketAddr::V4(addr) = listener.local_addr()? else {
+            unreachable!("bound to an ipv4 address")
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_accept = stop.clone();
+        thread::spawn(move || {
+            for (i, client) in listener.incoming().enumerate() {
+                if stop_accept.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Ok(client) = client else { continue };
+                if let Some(every) = config.drop_every {
+                    let every = every as usize;
+                    if i % every == every - 1 {
+                        debug!("proxy dropping connection {}", i);
+                        continue;
+                    }
+                }
+                let config = config.clone();
+                thread::spawn(move || forward(client, target, &config));
+            }
+        });
+        Ok(Proxy { addr, stop })
+    }
+
+    pub(crate) fn addr(&self) -> SocketAddrV4 {
+        self.addr
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // unblocks the accept loop
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+/// Forwards the requests of `client` to `target` and the responses back, delaying the first chunk
+/// of every response by [ProxyConfig::delay]. A connection is kept alive across many requests, a
+/// response is the data received after a request was forwarded
+fn forward(mut client: TcpStream, target: SocketAddrV4, config: &ProxyConfig) -> io::Result<()> {
+    let mut upstream = TcpStream::connect(target)?;
+    let mut client_read = client.try_clone()?;
+    let mut upstream_write = upstream.try_clone()?;
+    let requested = Arc::new(AtomicBool::new(false));
+    let set_requested = requested.clone();
+    thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        while let Ok(n) = client_read.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            // set before forwarding, so that it's seen by the time the response arrives
+            set_requested.store(true, Ordering::SeqCst);
+            if upstream_write.write_all(&buf[..n]).is_err() {
+                break;
+            }
+        }
+        let _ = upstream_write.shutdown(Shutdown::Write);
+    });
+
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = upstream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        if requested.swap(false, Ordering::SeqCst) {
+            thread::sleep(config.delay);
+        }
+        client.write_all(&buf[..n])?;
+    }
+    client.shutdown(Shutdown::Both)
+}
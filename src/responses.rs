@@ -0,0 +1,392 @@
+//! Response types of RPC calls not covered, or not fully covered, by [clightningrpc]
+
+use clightningrpc::types::MSat;
+use serde::Deserialize;
+
+/// 'newaddr' command, including the `p2tr` field missing in [clightningrpc::responses::NewAddr]
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewAddr {
+    pub bech32: Option<String>,
+    pub p2tr: Option<String>,
+}
+
+/// Sub-structure for peer in 'listpeers', unlike [clightningrpc::responses::Peer] it doesn't
+/// require the `channels` field removed in newer lightningd versions
+#[derive(Debug, Clone, Deserialize)]
+pub struct Peer {
+    pub connected: bool,
+    pub features: Option<String>,
+}
+
+/// 'listpeers' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListPeers {
+    pub peers: Vec<Peer>,
+}
+
+/// Sub-structure for pays in 'listpays'
+#[derive(Debug, Clone, Deserialize)]
+pub struct Pay {
+    pub payment_hash: String,
+    pub status: String,
+    pub created_at: u64,
+    pub destination: Option<String>,
+    pub completed_at: Option<u64>,
+    pub label: Option<String>,
+    pub bolt11: Option<String>,
+    pub bolt12: Option<String>,
+    pub amount_msat: Option<MSat>,
+    pub amount_sent_msat: Option<MSat>,
+    pub preimage: Option<String>,
+    pub number_of_parts: Option<u64>,
+}
+
+/// 'listpays' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListPays {
+    pub pays: Vec<Pay>,
+}
+
+/// Sub-structure for payments in 'listsendpays', every part of a multi-part payment is a
+/// separate entry sharing the `payment_hash` and `groupid`
+#[derive(Debug, Clone, Deserialize)]
+pub struct SendPay {
+    pub id: u64,
+    pub groupid: u64,
+    /// missing for payments not split in parts
+    pub partid: Option<u64>,
+    pub payment_hash: String,
+    /// "pending", "failed" or "complete"
+    pub status: String,
+    pub created_at: u64,
+    pub amount_msat: Option<MSat>,
+    pub amount_sent_msat: MSat,
+    pub destination: Option<String>,
+    pub label: Option<String>,
+    pub bolt11: Option<String>,
+    pub bolt12: Option<String>,
+    pub payment_preimage: Option<String>,
+    pub completed_at: Option<u64>,
+}
+
+/// 'listsendpays' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListSendPays {
+    pub payments: Vec<SendPay>,
+}
+
+/// Sub-structure for forwards in 'listforwards'
+#[derive(Debug, Clone, Deserialize)]
+pub struct Forward {
+    pub in_channel: String,
+    pub out_channel: Option<String>,
+    pub in_msat: MSat,
+    pub out_msat: Option<MSat>,
+    pub fee_msat: Option<MSat>,
+    /// eg. "offered", "settled", "failed", "local_failed"
+    pub status: String,
+    pub received_time: f64,
+    pub resolved_time: Option<f64>,
+}
+
+/// 'listforwards' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListForwards {
+    pub forwards: Vec<Forward>,
+}
+
+/// 'listchannels' command, only counting the channels so that it doesn't depend on fields
+/// changing across lightningd versions
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListChannels {
+    pub channels: Vec<serde::de::IgnoredAny>,
+}
+
+/// Sub-structure for channels in 'listchannels' when queried by `short_channel_id`
+#[derive(Debug, Clone, Deserialize)]
+pub struct GossipChannel {
+    pub short_channel_id: String,
+    pub public: bool,
+}
+
+/// 'listchannels' command when queried by `short_channel_id`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListGossipChannels {
+    pub channels: Vec<GossipChannel>,
+}
+
+/// 'waitblockheight' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct WaitBlockHeight {
+    pub blockheight: u64,
+}
+
+/// 'decode' command, common bolt11 fields are typed while every other field, like the bolt12
+/// specific ones, is kept in `other`
+#[derive(Debug, Clone, Deserialize)]
+pub struct DecodedInvoice {
+    /// eg. "bolt11 invoice", "bolt12 offer", "bolt12 invoice"
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub valid: bool,
+    pub amount_msat: Option<MSat>,
+    pub description: Option<String>,
+    pub expiry: Option<u64>,
+    pub created_at: Option<u64>,
+    pub payee: Option<String>,
+    pub payment_hash: Option<String>,
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Sub-structure for channels in 'listpeerchannels'
+#[derive(Debug, Clone, Deserialize)]
+pub struct PeerChannel {
+    pub peer_id: String,
+    pub peer_connected: bool,
+    /// eg. "CHANNELD_AWAITING_LOCKIN", "CHANNELD_NORMAL", "ONCHAIN"
+    pub state: String,
+    pub channel_id: Option<String>,
+    pub short_channel_id: Option<String>,
+    pub funding_txid: Option<String>,
+    pub funding_outnum: Option<u32>,
+    /// who funded the channel, "local" or "remote"
+    pub opener: Option<String>,
+    pub private: Option<bool>,
+    pub to_us_msat: Option<MSat>,
+    pub total_msat: Option<MSat>,
+    pub spendable_msat: Option<MSat>,
+    pub receivable_msat: Option<MSat>,
+    #[serde(default)]
+    pub status: Vec<String>,
+    #[serde(default)]
+    pub htlcs: Vec<Htlc>,
+    pub alias: Option<ChannelAlias>,
+    /// available since lightningd v24.02
+    pub updates: Option<ChannelUpdates>,
+}
+
+/// Sub-structure for alias of channels in 'listpeerchannels'
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChannelAlias {
+    pub local: Option<String>,
+    pub remote: Option<String>,
+}
+
+/// Sub-structure for updates of channels in 'listpeerchannels'
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChannelUpdates {
+    pub local: Option<ChannelUpdate>,
+    pub remote: Option<ChannelUpdate>,
+}
+
+/// Channel parameters of one direction in 'listpeerchannels' updates
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChannelUpdate {
+    pub fee_base_msat: MSat,
+    pub fee_proportional_millionths: u32,
+    pub cltv_expiry_delta: u32,
+}
+
+/// Sub-structure for in-flight htlcs of channels in 'listpeerchannels'
+#[derive(Debug, Clone, Deserialize)]
+pub struct Htlc {
+    /// "in" or "out"
+    pub direction: String,
+    pub id: u64,
+    pub amount_msat: MSat,
+    pub expiry: u32,
+    pub payment_hash: String,
+    /// eg. "SENT_ADD_HTLC", "RCVD_ADD_ACK_REVOCATION"
+    pub state: String,
+    pub local_trimmed: Option<bool>,
+}
+
+/// Sub-structure for htlcs in 'listhtlcs', including the resolved ones
+#[derive(Debug, Clone, Deserialize)]
+pub struct HistoricalHtlc {
+    pub short_channel_id: String,
+    pub id: u64,
+    pub expiry: u32,
+    pub amount_msat: MSat,
+    /// "in" or "out"
+    pub direction: String,
+    pub payment_hash: String,
+    /// eg. "SENT_REMOVE_ACK_REVOCATION", "RCVD_REMOVE_ACK_REVOCATION"
+    pub state: String,
+}
+
+/// 'listhtlcs' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListHtlcs {
+    pub htlcs: Vec<HistoricalHtlc>,
+}
+
+impl PeerChannel {
+    /// The short channel id if any, otherwise the channel id or the peer id
+    pub(crate) fn label(&self) -> &str {
+        self.short_channel_id
+            .as_deref()
+            .or(self.channel_id.as_deref())
+            .unwrap_or(&self.peer_id)
+    }
+}
+
+/// 'listpeerchannels' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListPeerChannels {
+    pub channels: Vec<PeerChannel>,
+}
+
+/// 'close' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct Close {
+    /// missing when the channel is closed before its funding is broadcast
+    pub txid: Option<String>,
+}
+
+/// 'fundpsbt' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct FundPsbt {
+    pub psbt: String,
+    pub feerate_per_kw: u32,
+    pub estimated_final_weight: u32,
+    pub excess_msat: MSat,
+    pub change_outnum: Option<u32>,
+}
+
+/// 'signpsbt' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct SignPsbt {
+    pub signed_psbt: String,
+}
+
+/// 'sendpsbt' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct SendPsbt {
+    pub tx: String,
+    pub txid: String,
+}
+
+/// 'splice_init' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpliceInit {
+    pub psbt: String,
+}
+
+/// 'splice_update' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpliceUpdate {
+    pub psbt: String,
+    pub commitments_secured: bool,
+}
+
+/// 'splice_signed' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpliceSigned {
+    pub tx: String,
+    pub txid: String,
+}
+
+/// Feerates of one style in 'feerates', unlike [clightningrpc::responses::FeeRatesInner] fields
+/// removed by newer lightningd versions aren't required. Estimate based rates are missing until
+/// bitcoind has fee estimates
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Feerates {
+    pub min_acceptable: Option<u64>,
+    pub max_acceptable: Option<u64>,
+    pub floor: Option<u64>,
+    pub opening: Option<u64>,
+    pub mutual_close: Option<u64>,
+    pub unilateral_close: Option<u64>,
+    pub penalty: Option<u64>,
+}
+
+/// 'feerates' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeeratesByStyle {
+    pub perkw: Option<Feerates>,
+    pub perkb: Option<Feerates>,
+}
+
+/// Sub-structure for log in 'getlog'
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogEntry {
+    /// eg. "BROKEN", "UNUSUAL", "INFO", "DEBUG", "SKIPPED"
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub time: Option<String>,
+    /// the subsystem logging, eg. "lightningd" or "chan#1"
+    pub source: Option<String>,
+    pub log: Option<String>,
+    pub node_id: Option<String>,
+}
+
+/// 'getlog' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct GetLog {
+    pub log: Vec<LogEntry>,
+}
+
+/// Sub-structure for balances in 'bkpr-listbalances'
+#[derive(Debug, Clone, Deserialize)]
+pub struct BkprBalance {
+    pub balance_msat: MSat,
+    /// eg. "bcrt"
+    pub coin_type: String,
+}
+
+/// Sub-structure for accounts in 'bkpr-listbalances'
+#[derive(Debug, Clone, Deserialize)]
+pub struct BkprAccount {
+    /// "wallet" or the channel id
+    pub account: String,
+    pub balances: Vec<BkprBalance>,
+    pub peer_id: Option<String>,
+    pub we_opened: Option<bool>,
+    pub account_closed: Option<bool>,
+    pub account_resolved: Option<bool>,
+}
+
+/// 'bkpr-listbalances' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct BkprListBalances {
+    pub accounts: Vec<BkprAccount>,
+}
+
+/// 'signmessage' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct Signature {
+    /// hex encoded signature
+    pub signature: String,
+    /// hex encoded recovery id
+    pub recid: String,
+    /// signature and recovery id zbase32 encoded, as expected by 'checkmessage'
+    pub zbase: String,
+}
+
+/// 'checkmessage' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct CheckMessage {
+    pub verified: bool,
+}
+
+/// Sub-structure for plugins in 'plugin list'
+#[derive(Debug, Clone, Deserialize)]
+pub struct Plugin {
+    pub name: String,
+    pub active: bool,
+}
+
+/// 'plugin list' command
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginList {
+    pub plugins: Vec<Plugin>,
+}
+
+/// 'holdinvoice' command of the holdinvoice plugin
+#[derive(Debug, Clone, Deserialize)]
+pub struct HoldInvoice {
+    pub bolt11: String,
+    pub payment_hash: String,
+}
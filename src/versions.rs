@@ -0,0 +1,27 @@
+#[cfg(all(feature = "download", not(feature = "download_23_11")))]
+compile_error!(
+    "`download` is internal, enable one of the `download_*` features to choose a version"
+);
+
+#[cfg(feature = "download_24_05")]
+pub const VERSION: &str = "v24.05";
+
+#[cfg(all(feature = "download_24_02", not(feature = "download_24_05")))]
+pub const VERSION: &str = "v24.02";
+
+#[cfg(all(feature = "download_23_11", not(feature = "download_24_02")))]
+pub const VERSION: &str = "v23.11";
+
+/// The sha256 of the release assets of [VERSION] as `(filename, hex)`, copied from the signed
+/// `SHA256SUMS-<version>` of the release. Every tarball is checked against them before extracting
+#[allow(dead_code)]
+#[cfg(feature = "download_24_05")]
+pub const SHA256: &[(&str, &str)] = &[];
+
+#[allow(dead_code)]
+#[cfg(all(feature = "download_24_02", not(feature = "download_24_05")))]
+pub const SHA256: &[(&str, &str)] = &[];
+
+#[allow(dead_code)]
+#[cfg(all(feature = "download_23_11", not(feature = "download_24_02")))]
+pub const SHA256: &[(&str, &str)] = &[];